@@ -38,6 +38,7 @@ use crate::tree_builder::tag_sets::*;
 use crate::tree_builder::types::*;
 use crate::util::str::to_escaped_string;
 use log::{debug, log_enabled, warn, Level};
+use markup5ever::matches_expanded;
 use mac::{_tt_as_expr_hack, format_if, matches};
 
 pub use self::PushFlag::*;
@@ -189,7 +190,7 @@ where
         opts: TreeBuilderOpts,
     ) -> TreeBuilder<Handle, Sink> {
         let doc_handle = sink.get_document();
-        let context_is_template = sink.elem_name(&context_elem) == expanded_name!(html "template");
+        let context_is_template = matches_expanded!(sink.elem_name(&context_elem), html "template");
         let mut tb = TreeBuilder {
             opts: opts,
             sink: sink,
@@ -105,6 +105,17 @@ fn named_entities_to_phf(to: &Path) {
         phf_map.entry(key, &format!("{:?}", value));
     }
 
+    // The replacement text of each entity, without the prefixes.
+    let mut replacements = phf_codegen::Map::new();
+    for &(name, cp1, cp2) in entities::NAMED_ENTITIES.iter() {
+        let text: String = [cp1, cp2]
+            .iter()
+            .filter(|&&cp| cp != 0)
+            .map(|&cp| std::char::from_u32(cp).unwrap())
+            .collect();
+        replacements.entry(&name[1..], &format!("{:?}", text));
+    }
+
     let mut file = File::create(to).unwrap();
     writeln!(
         &mut file,
@@ -128,4 +139,10 @@ fn named_entities_to_phf(to: &Path) {
         phf_map.build(),
     )
     .unwrap();
+    writeln!(
+        &mut file,
+        "static NAMED_ENTITY_REPLACEMENTS: Map<&'static str, &'static str> = {};",
+        replacements.build(),
+    )
+    .unwrap();
 }
@@ -48,3 +48,83 @@ pub static C1_REPLACEMENTS: [Option<char>; 32] = [
 ];
 
 include!(concat!(env!("OUT_DIR"), "/named_entities.rs"));
+
+/// Looks up an HTML named character reference by its name, without the
+/// leading `&` but with the `;` if it has one, and returns its replacement
+/// text, one or two characters. Like the spec, this knows the legacy names
+/// that work without a semicolon, such as `amp` and `nbsp`.
+///
+/// # Examples
+///
+/// ```
+/// use markup5ever::data::lookup_entity;
+///
+/// assert_eq!(lookup_entity("amp;"), Some("&"));
+/// assert_eq!(lookup_entity("ampere;"), None);
+/// ```
+pub fn lookup_entity(name: &str) -> Option<&'static str> {
+    NAMED_ENTITY_REPLACEMENTS.get(name).cloned()
+}
+
+/// Finds the longest named character reference at the start of `input`,
+/// which follows the `&`, and returns its length in bytes and its
+/// replacement text. As in the spec, `notin;` wins over the legacy `not`
+/// when both match.
+///
+/// # Examples
+///
+/// ```
+/// use markup5ever::data::match_entity;
+///
+/// assert_eq!(match_entity("notin; x"), Some((6, "\u{2209}")));
+/// assert_eq!(match_entity("notit;"), Some((3, "\u{AC}")));
+/// ```
+pub fn match_entity(input: &str) -> Option<(usize, &'static str)> {
+    let mut longest = None;
+    // NAMED_ENTITIES also has every prefix of a name, so the scan can stop
+    // as soon as no name starts with what has been read.
+    for (i, _) in input.char_indices() {
+        let end = i + input[i..].chars().next().map_or(0, char::len_utf8);
+        let candidate = &input[..end];
+        if !NAMED_ENTITIES.contains_key(candidate) {
+            break;
+        }
+        if let Some(text) = lookup_entity(candidate) {
+            longest = Some((end, text));
+        }
+    }
+    longest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lookup_entity, match_entity};
+
+    #[test]
+    fn entities() {
+        assert_eq!(lookup_entity("amp;"), Some("&"));
+        assert_eq!(lookup_entity("amp"), Some("&"));
+        assert_eq!(lookup_entity("nbsp;"), Some("\u{A0}"));
+        assert_eq!(lookup_entity("nbsp"), Some("\u{A0}"));
+        assert_eq!(lookup_entity("not"), Some("\u{AC}"));
+        assert_eq!(lookup_entity("notinE;"), Some("\u{22F9}\u{338}"));
+        assert_eq!(lookup_entity("notinE"), None);
+        assert_eq!(lookup_entity("noti"), None);
+        assert_eq!(lookup_entity("bogus;"), None);
+        assert_eq!(lookup_entity(""), None);
+    }
+
+    #[test]
+    fn longest_match() {
+        assert_eq!(match_entity("not"), Some((3, "\u{AC}")));
+        assert_eq!(match_entity("not;"), Some((4, "\u{AC}")));
+        assert_eq!(match_entity("notin;"), Some((6, "\u{2209}")));
+        assert_eq!(match_entity("notin"), Some((3, "\u{AC}")));
+        assert_eq!(match_entity("notinE;..."), Some((7, "\u{22F9}\u{338}")));
+        assert_eq!(match_entity("amp;amp;"), Some((4, "&")));
+        assert_eq!(match_entity("ampère"), Some((3, "&")));
+        assert_eq!(match_entity("no"), None);
+        assert_eq!(match_entity("bogus;"), None);
+        assert_eq!(match_entity(""), None);
+    }
+}
@@ -0,0 +1,306 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Helpers for building and tidying up lists of [`Attribute`]s.
+//!
+//! These are mostly conveniences for constructing test trees and simple templates; the
+//! tokenizers do not use them.
+//!
+//! [`Attribute`]: ../struct.Attribute.html
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+
+use super::{Attribute, QualName};
+use crate::LocalName;
+
+/// The reason a string could not be parsed by [`parse_attributes`].
+///
+/// Positions are byte offsets into the parsed string.
+///
+/// [`parse_attributes`]: fn.parse_attributes.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AttrParseError {
+    /// An attribute name was expected at the given position.
+    ExpectedName(usize),
+    /// An `=` was expected after the attribute name at the given position.
+    ExpectedEquals(usize),
+    /// An opening `"` or `'` was expected at the given position.
+    ExpectedQuote(usize),
+    /// The value opened at the given position has no matching closing quote.
+    UnterminatedValue(usize),
+    /// Two attributes were not separated by whitespace at the given position.
+    ExpectedWhitespace(usize),
+    /// The same attribute name was given more than once.
+    DuplicateName(LocalName),
+}
+
+impl fmt::Display for AttrParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AttrParseError::ExpectedName(pos) => write!(f, "expected attribute name at {}", pos),
+            AttrParseError::ExpectedEquals(pos) => write!(f, "expected '=' at {}", pos),
+            AttrParseError::ExpectedQuote(pos) => write!(f, "expected quote at {}", pos),
+            AttrParseError::UnterminatedValue(pos) => {
+                write!(f, "attribute value starting at {} is not terminated", pos)
+            },
+            AttrParseError::ExpectedWhitespace(pos) => {
+                write!(f, "expected whitespace between attributes at {}", pos)
+            },
+            AttrParseError::DuplicateName(ref name) => {
+                write!(f, "attribute '{}' is given more than once", name)
+            },
+        }
+    }
+}
+
+impl Error for AttrParseError {}
+
+/// Parses a list of attributes written as `name="value" name2='value2'`.
+///
+/// Every attribute ends up in the null namespace without a prefix. Values must be quoted
+/// (either quote style is fine) and are taken verbatim, so no character references are
+/// decoded.
+///
+/// # Examples
+///
+/// ```
+/// use markup5ever::interface::parse_attributes;
+///
+/// let attrs = parse_attributes(r#"id="main" class='a b'"#).unwrap();
+/// assert_eq!(&*attrs[0].name.local, "id");
+/// assert_eq!(&*attrs[1].value, "a b");
+/// ```
+pub fn parse_attributes(s: &str) -> Result<Vec<Attribute>, AttrParseError> {
+    let mut attrs: Vec<Attribute> = vec![];
+    let bytes = s.as_bytes();
+    let mut pos = 0;
+
+    loop {
+        let start = pos;
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if pos == bytes.len() {
+            return Ok(attrs);
+        }
+        if !attrs.is_empty() && pos == start {
+            return Err(AttrParseError::ExpectedWhitespace(pos));
+        }
+
+        let name_start = pos;
+        while pos < bytes.len() && !is_name_terminator(bytes[pos]) {
+            pos += 1;
+        }
+        if pos == name_start {
+            return Err(AttrParseError::ExpectedName(pos));
+        }
+        let name = LocalName::from(&s[name_start..pos]);
+
+        if bytes.get(pos) != Some(&b'=') {
+            return Err(AttrParseError::ExpectedEquals(pos));
+        }
+        pos += 1;
+
+        let quote = match bytes.get(pos) {
+            Some(&q) if q == b'"' || q == b'\'' => q,
+            _ => return Err(AttrParseError::ExpectedQuote(pos)),
+        };
+        let value_start = pos + 1;
+        let value_len = match bytes[value_start..].iter().position(|&b| b == quote) {
+            Some(len) => len,
+            None => return Err(AttrParseError::UnterminatedValue(pos)),
+        };
+        pos = value_start + value_len + 1;
+
+        if attrs.iter().any(|attr| attr.name.local == name) {
+            return Err(AttrParseError::DuplicateName(name));
+        }
+        attrs.push(Attribute {
+            name: QualName::new(None, ns!(), name),
+            value: s[value_start..value_start + value_len].into(),
+        });
+    }
+}
+
+/// Removes attributes whose namespace and local name are the same as an
+/// earlier one's, keeping the first and the order of the rest.
+///
+/// Serializers that insist on well-formed output reject elements with such
+/// duplicates; this cleans the list up beforehand.
+///
+/// # Examples
+///
+/// ```
+/// use markup5ever::interface::{dedup_attributes, parse_attributes};
+///
+/// let mut attrs = parse_attributes(r#"id="a" class="b""#).unwrap();
+/// attrs.extend(parse_attributes(r#"id="c""#).unwrap());
+/// dedup_attributes(&mut attrs);
+/// assert_eq!(attrs, parse_attributes(r#"id="a" class="b""#).unwrap());
+/// ```
+pub fn dedup_attributes(attrs: &mut Vec<Attribute>) {
+    let mut seen = HashSet::new();
+    attrs.retain(|attr| seen.insert((attr.name.ns.clone(), attr.name.local.clone())));
+}
+
+/// Sorts attributes into the order of [Canonical XML], with
+/// [`canonical_attribute_order`].
+///
+/// [Canonical XML]: https://www.w3.org/TR/xml-c14n/#DocumentOrder
+/// [`canonical_attribute_order`]: fn.canonical_attribute_order.html
+pub fn canonical_sort(attrs: &mut Vec<Attribute>) {
+    attrs.sort_by(|a, b| canonical_attribute_order(&a.name, &b.name));
+}
+
+/// Compares attribute names in the order Canonical XML writes them.
+///
+/// Namespace declarations come first, the default namespace before the
+/// others, which are ordered by prefix. The remaining attributes are
+/// ordered by namespace URI and then local name, so those in no namespace
+/// come before the rest. Prefixes are not compared.
+pub fn canonical_attribute_order(a: &QualName, b: &QualName) -> Ordering {
+    fn key(name: &QualName) -> (bool, &str, &str) {
+        if name.ns == ns!(xmlns) {
+            let prefix = if name.prefix.is_some() {
+                &*name.local
+            } else {
+                ""
+            };
+            (false, "", prefix)
+        } else {
+            (true, &*name.ns, &*name.local)
+        }
+    }
+    key(a).cmp(&key(b))
+}
+
+fn is_name_terminator(b: u8) -> bool {
+    b.is_ascii_whitespace() || b == b'=' || b == b'"' || b == b'\''
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonical_sort, dedup_attributes, parse_attributes, AttrParseError};
+    use crate::{Attribute, LocalName, Prefix, QualName};
+
+    #[test]
+    fn parses_both_quote_styles() {
+        let attrs = parse_attributes(" a=\"1\"\tb='two words' c=\"it's\" ").unwrap();
+        let pairs: Vec<_> = attrs
+            .iter()
+            .map(|attr| (&*attr.name.local, &*attr.value))
+            .collect();
+        assert_eq!(pairs, vec![("a", "1"), ("b", "two words"), ("c", "it's")]);
+        assert!(attrs
+            .iter()
+            .all(|attr| attr.name.prefix.is_none() && attr.name.ns == ns!()));
+    }
+
+    #[test]
+    fn empty_input() {
+        assert_eq!(parse_attributes("").unwrap(), vec![]);
+        assert_eq!(parse_attributes("  ").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn rejects_mismatched_quotes() {
+        assert_eq!(
+            parse_attributes("a=\"1'"),
+            Err(AttrParseError::UnterminatedValue(2))
+        );
+        assert_eq!(
+            parse_attributes("a='1\" b='2'"),
+            Err(AttrParseError::ExpectedWhitespace(9))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert_eq!(
+            parse_attributes("a"),
+            Err(AttrParseError::ExpectedEquals(1))
+        );
+        assert_eq!(
+            parse_attributes("a=1"),
+            Err(AttrParseError::ExpectedQuote(2))
+        );
+        assert_eq!(
+            parse_attributes("=\"1\""),
+            Err(AttrParseError::ExpectedName(0))
+        );
+        assert_eq!(
+            parse_attributes("a=\"1\"b=\"2\""),
+            Err(AttrParseError::ExpectedWhitespace(5))
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_names() {
+        assert_eq!(
+            parse_attributes("id=\"a\" class=\"b\" id='c'"),
+            Err(AttrParseError::DuplicateName(LocalName::from("id")))
+        );
+    }
+
+    #[test]
+    fn dedup_keeps_first_occurrence() {
+        let attr = |prefix: Option<&str>, ns, local: &str, value: &str| Attribute {
+            name: QualName::new(prefix.map(Prefix::from), ns, LocalName::from(local)),
+            value: value.into(),
+        };
+        let mut attrs = vec![
+            attr(None, ns!(), "id", "first"),
+            attr(Some("xlink"), ns!(xlink), "href", "a"),
+            attr(None, ns!(), "class", "c"),
+            attr(None, ns!(), "id", "second"),
+            attr(Some("l"), ns!(xlink), "href", "b"),
+            attr(Some("xml"), ns!(xml), "id", "x"),
+        ];
+        dedup_attributes(&mut attrs);
+        let values: Vec<_> = attrs.iter().map(|attr| &*attr.value).collect();
+        assert_eq!(values, vec!["first", "a", "c", "x"]);
+    }
+
+    #[test]
+    fn canonical_sort_orders_by_namespace_then_name() {
+        let attr = |prefix: Option<&str>, ns: &str, local: &str| Attribute {
+            name: QualName::new(
+                prefix.map(Prefix::from),
+                crate::Namespace::from(ns),
+                LocalName::from(local),
+            ),
+            value: "".into(),
+        };
+        let mut attrs = vec![
+            attr(Some("z"), "urn:a", "attr"),
+            attr(None, "", "b"),
+            attr(Some("xmlns"), &ns!(xmlns), "z"),
+            attr(Some("a"), "urn:b", "attr"),
+            attr(None, "", "a"),
+            attr(Some("xmlns"), &ns!(xmlns), "a"),
+            attr(None, &ns!(xmlns), "xmlns"),
+            attr(Some("z"), "urn:a", "_"),
+        ];
+        canonical_sort(&mut attrs);
+        let names: Vec<_> = attrs
+            .iter()
+            .map(|attr| match attr.name.prefix {
+                Some(ref prefix) => format!("{}:{}", prefix, attr.name.local),
+                None => attr.name.local.to_string(),
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec!["xmlns", "xmlns:a", "xmlns:z", "a", "b", "z:_", "z:attr", "a:attr"]
+        );
+    }
+}
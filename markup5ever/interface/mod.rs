@@ -11,6 +11,8 @@
 use std::fmt;
 use tendril::StrTendril;
 
+pub use self::attrs::{canonical_attribute_order, canonical_sort};
+pub use self::attrs::{dedup_attributes, parse_attributes, AttrParseError};
 pub use self::tree_builder::{create_element, AppendNode, AppendText, ElementFlags, NodeOrText};
 pub use self::tree_builder::{LimitedQuirks, NoQuirks, Quirks, QuirksMode};
 pub use self::tree_builder::{NextParserState, Tracer, TreeSink};
@@ -41,6 +43,43 @@ impl<'a> fmt::Debug for ExpandedName<'a> {
     }
 }
 
+/// Writes the name in Clark notation, `{namespace}local`, with empty
+/// braces for no namespace.
+impl<'a> fmt::Display for ExpandedName<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{{}}}{}", self.ns, self.local)
+    }
+}
+
+impl<'a> ExpandedName<'a> {
+    /// Parses a name in Clark notation, as written by the `Display` impl.
+    /// Since an `ExpandedName` only borrows its parts, they are returned
+    /// as atoms. A name without braces is in no namespace.
+    ///
+    /// ```
+    /// #[macro_use] extern crate markup5ever;
+    /// use markup5ever::ExpandedName;
+    ///
+    /// # fn main() {
+    /// let (ns, local) =
+    ///     ExpandedName::from_clark_notation("{http://www.w3.org/2000/svg}rect").unwrap();
+    /// assert_eq!(ExpandedName { ns: &ns, local: &local }, expanded_name!(svg "rect"));
+    /// # }
+    /// ```
+    pub fn from_clark_notation(name: &str) -> Option<(Namespace, LocalName)> {
+        let (ns, local) = if name.starts_with('{') {
+            let end = name.find('}')?;
+            (&name[1..end], &name[end + 1..])
+        } else {
+            ("", name)
+        };
+        if local.is_empty() || local.contains(|c| c == '{' || c == '}') {
+            return None;
+        }
+        Some((Namespace::from(ns), LocalName::from(local)))
+    }
+}
+
 /// Helper to quickly create an expanded name.
 ///
 /// Can be used with no namespace as `expanded_name!("", "some_name")`
@@ -91,6 +130,70 @@ macro_rules! expanded_name {
     };
 }
 
+/// Checks whether a name matches one of the given expanded names.
+///
+/// The name can be a [`QualName`] (whose prefix is ignored) or an
+/// [`ExpandedName`]. The namespace is written once, as for
+/// [`expanded_name!`], followed by one or more local names separated by `|`.
+///
+/// [`QualName`]: interface/struct.QualName.html
+/// [`ExpandedName`]: interface/struct.ExpandedName.html
+/// [`expanded_name!`]: macro.expanded_name.html
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate markup5ever;
+///
+/// # fn main() {
+/// use markup5ever::QualName;
+///
+/// let name = QualName::new(None, ns!(html), local_name!("template"));
+/// assert!(matches_expanded!(name, html "template"));
+/// assert!(matches_expanded!(name, html "br" | "template"));
+/// assert!(!matches_expanded!(name, svg "template"));
+/// assert!(!matches_expanded!(name.expanded(), "", "template"));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! matches_expanded {
+    ($name: expr, "", $($local: tt)|+) => {
+        match $crate::interface::AsExpandedName::as_expanded_name(&$name) {
+            $( $crate::expanded_name!("", $local) )|+ => true,
+            _ => false,
+        }
+    };
+    ($name: expr, $ns: ident $($local: tt)|+) => {
+        match $crate::interface::AsExpandedName::as_expanded_name(&$name) {
+            $( $crate::expanded_name!($ns $local) )|+ => true,
+            _ => false,
+        }
+    };
+}
+
+/// Names that can be viewed as an [`ExpandedName`], for
+/// [`matches_expanded!`].
+///
+/// [`ExpandedName`]: struct.ExpandedName.html
+/// [`matches_expanded!`]: ../macro.matches_expanded.html
+#[doc(hidden)]
+pub trait AsExpandedName {
+    fn as_expanded_name(&self) -> ExpandedName<'_>;
+}
+
+impl AsExpandedName for QualName {
+    fn as_expanded_name(&self) -> ExpandedName<'_> {
+        self.expanded()
+    }
+}
+
+impl<'a> AsExpandedName for ExpandedName<'a> {
+    fn as_expanded_name(&self) -> ExpandedName<'_> {
+        *self
+    }
+}
+
+pub mod attrs;
 pub mod tree_builder;
 
 /// A fully qualified name (with a namespace), used to depict names of tags and attributes.
@@ -310,6 +413,49 @@ impl QualName {
         }
     }
 
+    /// Parses a name as written in markup, like `xlink:href`, looking up
+    /// the namespace of its prefix with `resolver`.
+    ///
+    /// An unprefixed name is in the default namespace, which `resolver` is
+    /// asked for with an empty prefix; when it has none, the name is in no
+    /// namespace. Returns `None` for an unknown prefix, or if the prefix or
+    /// local part is empty or there is more than one colon.
+    ///
+    /// ```
+    /// #[macro_use] extern crate markup5ever;
+    /// use markup5ever::{Namespace, Prefix, QualName};
+    ///
+    /// # fn main() {
+    /// let resolver = |prefix: &Prefix| match &**prefix {
+    ///     "xlink" => Some(ns!(xlink)),
+    ///     _ => None,
+    /// };
+    /// let name = QualName::parse("xlink:href", resolver).unwrap();
+    /// assert_eq!(name.prefix, Some(Prefix::from("xlink")));
+    /// assert_eq!(name.expanded(), expanded_name!(xlink "href"));
+    /// assert_eq!(QualName::parse("svg:rect", resolver), None);
+    /// # }
+    /// ```
+    pub fn parse(name: &str, resolver: impl Fn(&Prefix) -> Option<Namespace>) -> Option<QualName> {
+        let mut parts = name.splitn(2, ':');
+        let first = parts.next().unwrap_or("");
+        match parts.next() {
+            Some(local) => {
+                if first.is_empty() || local.is_empty() || local.contains(':') {
+                    return None;
+                }
+                let prefix = Prefix::from(first);
+                let ns = resolver(&prefix)?;
+                Some(QualName::new(Some(prefix), ns, LocalName::from(local)))
+            },
+            None if first.is_empty() => None,
+            None => {
+                let ns = resolver(&namespace_prefix!("")).unwrap_or(ns!());
+                Some(QualName::new(None, ns, LocalName::from(first)))
+            },
+        }
+    }
+
     /// Take a reference of `self` as an `ExpandedName`, dropping the unresolved prefix.
     ///
     /// In XML and HTML prefixes are only used to extract the relevant namespace URI.
@@ -330,8 +476,23 @@ impl QualName {
     /// }
     /// ```
     ///
+    /// The result borrows the namespace and local name from `self` rather than
+    /// cloning the atoms, so it is cheap to create for a comparison but cannot
+    /// outlive the `QualName`. It can be matched against `expanded_name!`:
+    ///
+    /// ```
+    /// # #[macro_use] extern crate markup5ever;
+    /// # use markup5ever::QualName;
+    /// # fn main() {
+    /// let name = QualName::new(None, ns!(html), local_name!("template"));
+    /// assert!(match name.expanded() {
+    ///     expanded_name!(html "template") => true,
+    ///     _ => false,
+    /// });
+    /// # }
+    /// ```
     #[inline]
-    pub fn expanded(&self) -> ExpandedName {
+    pub fn expanded(&self) -> ExpandedName<'_> {
         ExpandedName {
             ns: &self.ns,
             local: &self.local,
@@ -355,7 +516,7 @@ pub struct Attribute {
 
 #[cfg(test)]
 mod tests {
-    use super::Namespace;
+    use super::{ExpandedName, Namespace, Prefix, QualName};
 
     #[test]
     fn ns_macro() {
@@ -374,4 +535,84 @@ mod tests {
             Namespace::from("http://www.w3.org/1998/Math/MathML")
         );
     }
+
+    #[test]
+    fn expanded_ignores_prefix() {
+        let name =
+            |prefix: Option<&str>, ns| QualName::new(prefix.map(From::from), ns, local_name!("a"));
+        let is_svg_a = |name: &QualName| match name.expanded() {
+            expanded_name!(svg "a") => true,
+            _ => false,
+        };
+        assert!(is_svg_a(&name(None, ns!(svg))));
+        assert!(is_svg_a(&name(Some("s"), ns!(svg))));
+        assert!(!is_svg_a(&name(None, ns!(html))));
+    }
+
+    #[test]
+    fn parse_resolves_prefix() {
+        let resolver = |prefix: &Prefix| match &**prefix {
+            "" => Some(ns!(svg)),
+            "xlink" => Some(ns!(xlink)),
+            _ => None,
+        };
+        assert_eq!(
+            QualName::parse("xlink:href", resolver),
+            Some(QualName::new(
+                Some(Prefix::from("xlink")),
+                ns!(xlink),
+                local_name!("href")
+            ))
+        );
+        assert_eq!(
+            QualName::parse("rect", resolver),
+            Some(QualName::new(None, ns!(svg), local_name!("rect")))
+        );
+        assert_eq!(
+            QualName::parse("rect", |_: &Prefix| None),
+            Some(QualName::new(None, ns!(), local_name!("rect")))
+        );
+        assert_eq!(QualName::parse("foo:bar", resolver), None);
+        for name in &["", ":a", "a:", "xlink:a:b"] {
+            assert_eq!(QualName::parse(name, resolver), None);
+        }
+    }
+
+    #[test]
+    fn clark_notation_round_trip() {
+        for name in &[expanded_name!(svg "rect"), expanded_name!("", "div")] {
+            let written = name.to_string();
+            let (ns, local) = ExpandedName::from_clark_notation(&written).unwrap();
+            assert_eq!(
+                ExpandedName {
+                    ns: &ns,
+                    local: &local
+                },
+                *name
+            );
+        }
+        assert_eq!(expanded_name!("", "div").to_string(), "{}div");
+        assert_eq!(
+            expanded_name!(html "p").to_string(),
+            "{http://www.w3.org/1999/xhtml}p"
+        );
+
+        assert_eq!(
+            ExpandedName::from_clark_notation("div"),
+            Some((ns!(), local_name!("div")))
+        );
+        for name in &["", "{}", "{urn:a", "{urn:a}", "a}b", "{urn:a}b{c}"] {
+            assert_eq!(ExpandedName::from_clark_notation(name), None);
+        }
+    }
+
+    #[test]
+    fn matches_expanded_checks_namespace() {
+        let name = QualName::new(None, ns!(html), local_name!("template"));
+        assert!(matches_expanded!(name, html "template"));
+        assert!(matches_expanded!(name.expanded(), html "br" | "template"));
+        assert!(!matches_expanded!(name, svg "template"));
+        assert!(!matches_expanded!(name, "", "template"));
+        assert!(!matches_expanded!(name, html "br"));
+    }
 }
@@ -9,7 +9,7 @@
 
 pub use tendril;
 
-/// Create a [`SmallCharSet`], with each space-separated number stored in the set.
+/// Create a [`SmallCharSet`], with each space-separated number or character stored in the set.
 ///
 /// # Examples
 ///
@@ -19,16 +19,35 @@ pub use tendril;
 /// let set = small_char_set!(12 54 42);
 /// assert_eq!(set.bits,
 ///            0b00000000_01000000_00000100_00000000_00000000_00000000_00010000_00000000);
+///
+/// // Character literals give the same set as their code points, and both can be mixed.
+/// assert_eq!(small_char_set!('\t' '\n' '\r' ' ').bits, small_char_set!(9 10 13 32).bits);
+/// assert_eq!(small_char_set!('\n' 32).bits, small_char_set!(10 ' ').bits);
+/// # }
+/// ```
+///
+/// Values must be below 64, anything else is a compile error:
+///
+/// ```compile_fail
+/// # #[macro_use] extern crate markup5ever;
+/// # fn main() {
+/// let set = small_char_set!('<' 64);
 /// # }
 /// ```
 ///
 /// [`SmallCharSet`]: struct.SmallCharSet.html
 #[macro_export]
-macro_rules! small_char_set ( ($($e:expr)+) => (
+macro_rules! small_char_set ( ($($e:expr)+) => ({
+    $(
+        const _: () = assert!(
+            ($e as usize) < 64,
+            concat!("small_char_set! value out of range: ", stringify!($e))
+        );
+    )+
     $ crate ::SmallCharSet {
         bits: $( (1 << ($e as usize)) )|+
     }
-));
+}));
 
 include!(concat!(env!("OUT_DIR"), "/generated.rs"));
 
@@ -38,9 +57,115 @@ pub mod interface;
 pub mod serialize;
 mod util {
     pub mod buffer_queue;
+    pub mod eq_str;
     pub mod smallcharset;
+    pub mod xml_chars;
 }
 
 pub use interface::{Attribute, ExpandedName, QualName};
+pub use util::eq_str::EqStr;
 pub use util::smallcharset::SmallCharSet;
 pub use util::*;
+
+/// The HTML void elements, which have a start tag but no content or end
+/// tag.
+pub const VOID_ELEMENTS: &[LocalName] = &[
+    local_name!("area"),
+    local_name!("base"),
+    local_name!("basefont"),
+    local_name!("bgsound"),
+    local_name!("br"),
+    local_name!("col"),
+    local_name!("embed"),
+    local_name!("frame"),
+    local_name!("hr"),
+    local_name!("img"),
+    local_name!("input"),
+    local_name!("keygen"),
+    local_name!("link"),
+    local_name!("meta"),
+    local_name!("param"),
+    local_name!("source"),
+    local_name!("track"),
+    local_name!("wbr"),
+];
+
+/// Whether `local` is one of the [`VOID_ELEMENTS`].
+///
+/// This only looks at the local name; callers should check that the
+/// element is in the HTML namespace.
+///
+/// [`VOID_ELEMENTS`]: constant.VOID_ELEMENTS.html
+pub fn is_void_element(local: &LocalName) -> bool {
+    VOID_ELEMENTS.contains(local)
+}
+
+/// Like [`is_void_element`], but for a name that has not been interned,
+/// compared ASCII-case-insensitively.
+///
+/// [`is_void_element`]: fn.is_void_element.html
+pub fn is_void_element_str(name: &str) -> bool {
+    VOID_ELEMENTS
+        .iter()
+        .any(|local| local.as_ref().eq_ignore_ascii_case(name))
+}
+
+/// Whether `local` names a raw text element, `script` or `style`, whose
+/// content is not escaped.
+///
+/// Like [`is_void_element`], this ignores the namespace.
+///
+/// [`is_void_element`]: fn.is_void_element.html
+pub fn is_raw_text_element(local: &LocalName) -> bool {
+    match *local {
+        local_name!("script") | local_name!("style") => true,
+        _ => false,
+    }
+}
+
+/// Whether `local` names an escapable raw text element, `textarea` or
+/// `title`, whose content may contain character references but no
+/// elements.
+pub fn is_escapable_raw_text_element(local: &LocalName) -> bool {
+    match *local {
+        local_name!("textarea") | local_name!("title") => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn void_elements() {
+        for local in VOID_ELEMENTS {
+            assert!(is_void_element(local), "{}", local);
+        }
+        assert!(!is_void_element(&local_name!("div")));
+        assert!(!is_void_element(&local_name!("template")));
+    }
+
+    #[test]
+    fn void_element_str_ignores_case() {
+        assert!(is_void_element_str("br"));
+        assert!(is_void_element_str("BR"));
+        assert!(is_void_element_str("ImG"));
+        assert!(!is_void_element_str("div"));
+        assert!(!is_void_element_str("brr"));
+        assert!(!is_void_element_str(""));
+    }
+
+    #[test]
+    fn raw_text_elements() {
+        assert!(is_raw_text_element(&local_name!("script")));
+        assert!(is_raw_text_element(&local_name!("style")));
+        assert!(!is_raw_text_element(&local_name!("textarea")));
+        assert!(!is_raw_text_element(&local_name!("div")));
+
+        assert!(is_escapable_raw_text_element(&local_name!("textarea")));
+        assert!(is_escapable_raw_text_element(&local_name!("title")));
+        assert!(!is_escapable_raw_text_element(&local_name!("script")));
+        assert!(!is_escapable_raw_text_element(&local_name!("p")));
+    }
+}
@@ -60,9 +60,52 @@ pub trait Serializer {
     /// Serialize a doctype node, for example `<!doctype html>`.
     fn write_doctype(&mut self, name: &str) -> io::Result<()>;
 
+    /// Serialize a doctype node with its public and system identifiers, for
+    /// example `<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Strict//EN"
+    /// "http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd">`.
+    ///
+    /// HTML serialization leaves the identifiers out, so by default only the
+    /// name is written.
+    fn write_doctype_full(
+        &mut self,
+        name: &str,
+        public_id: Option<&str>,
+        system_id: Option<&str>,
+    ) -> io::Result<()> {
+        let _ = (public_id, system_id);
+        self.write_doctype(name)
+    }
+
     /// Serialize a processing instruction node, for example
     /// `<?xml-stylesheet type="text/xsl" href="style.xsl"?>`.
     fn write_processing_instruction(&mut self, target: &str, data: &str) -> io::Result<()>;
+
+    /// Serialize a CDATA section, for example `<![CDATA[ <b> ]]>`.
+    ///
+    /// HTML has no CDATA sections outside foreign content, so by default the
+    /// text is written as a plain text node.
+    fn write_cdata(&mut self, text: &str) -> io::Result<()> {
+        self.write_text(text)
+    }
+
+    /// Write already serialized markup as is, for example a cached subtree.
+    /// The caller is responsible for it being well-formed.
+    ///
+    /// Serializers that cannot do this return an error, which is the
+    /// default.
+    fn write_raw(&mut self, raw: &str) -> io::Result<()> {
+        let _ = raw;
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "this serializer cannot write raw markup",
+        ))
+    }
+
+    /// Write out anything the serializer or its writer has buffered. Called
+    /// once the whole node has been serialized.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
 }
 
 /// A type alias for an attribute name and value (e.g. the `class="test"` in `<div class="test">`
@@ -18,6 +18,7 @@
 //!
 //! [`BufferQueue`]: struct.BufferQueue.html
 
+use std::cmp;
 use std::collections::VecDeque;
 
 use tendril::StrTendril;
@@ -103,6 +104,67 @@ impl BufferQueue {
         self.buffers.front().map(|b| b.chars().next().unwrap())
     }
 
+    /// Whether the queued input starts with `s`, which may span several
+    /// buffers, without removing anything from the queue.
+    ///
+    /// Unlike [`eat`], this returns `false` both when the input differs and
+    /// when there is not enough of it yet.
+    ///
+    /// [`eat`]: #method.eat
+    pub fn starts_with(&self, s: &str) -> bool {
+        let mut rest = s.as_bytes();
+        for buf in self.buffers.iter() {
+            if rest.is_empty() {
+                break;
+            }
+            let n = cmp::min(buf.len(), rest.len());
+            if buf.as_bytes()[..n] != rest[..n] {
+                return false;
+            }
+            rest = &rest[n..];
+        }
+        rest.is_empty()
+    }
+
+    /// Removes and returns the next `n` bytes of input, which may span
+    /// several buffers.
+    ///
+    /// Returns `None`, leaving the queue as it is, if fewer than `n` bytes
+    /// are queued or if the `n`th byte is in the middle of a character.
+    pub fn pop_front_bytes(&mut self, n: usize) -> Option<StrTendril> {
+        // Check before removing anything.
+        let mut rest = n;
+        for buf in self.buffers.iter() {
+            if rest <= buf.len() {
+                if !buf.is_char_boundary(rest) {
+                    return None;
+                }
+                rest = 0;
+                break;
+            }
+            rest -= buf.len();
+        }
+        if rest > 0 {
+            return None;
+        }
+
+        let mut out = StrTendril::new();
+        let mut rest = n;
+        while rest > 0 {
+            let buf = self.buffers.front_mut().expect("queue ran out of bytes");
+            if buf.len() <= rest {
+                rest -= buf.len();
+                out.push_tendril(buf);
+                self.buffers.pop_front();
+            } else {
+                out.push_tendril(&buf.subtendril(0, rest as u32));
+                buf.pop_front(rest as u32);
+                rest = 0;
+            }
+        }
+        Some(out)
+    }
+
     /// Get the next character if one is available, removing it from the queue.
     ///
     /// This function manages the buffers, removing them as they become empty.
@@ -288,6 +350,76 @@ mod test {
         assert_eq!(pop(), None);
     }
 
+    #[test]
+    fn pop_except_set_at_buffer_boundaries() {
+        let mut bq = BufferQueue::new();
+        bq.push_back("ab".to_tendril());
+        bq.push_back("&".to_tendril());
+        bq.push_back("c".to_tendril());
+        bq.push_back("d<".to_tendril());
+        let mut pop = || bq.pop_except_from(small_char_set!('&' '<'));
+        assert_eq!(pop(), Some(NotFromSet("ab".to_tendril())));
+        assert_eq!(pop(), Some(FromSet('&')));
+        // Runs are not joined across buffers.
+        assert_eq!(pop(), Some(NotFromSet("c".to_tendril())));
+        assert_eq!(pop(), Some(NotFromSet("d".to_tendril())));
+        assert_eq!(pop(), Some(FromSet('<')));
+        assert_eq!(pop(), None);
+        assert!(bq.is_empty());
+    }
+
+    #[test]
+    fn pop_front_bytes_across_buffers() {
+        let mut bq = BufferQueue::new();
+        bq.push_back("ab".to_tendril());
+        bq.push_back("cdé".to_tendril());
+        bq.push_back("f".to_tendril());
+        assert_eq!(bq.pop_front_bytes(0), Some("".to_tendril()));
+        assert_eq!(bq.pop_front_bytes(3), Some("abc".to_tendril()));
+        // 'é' takes two bytes, so this would split it.
+        assert_eq!(bq.pop_front_bytes(2), None);
+        assert_eq!(bq.pop_front_bytes(6), None);
+        assert_eq!(bq.peek(), Some('d'));
+        assert_eq!(bq.pop_front_bytes(3), Some("dé".to_tendril()));
+        assert_eq!(bq.pop_front_bytes(1), Some("f".to_tendril()));
+        assert!(bq.is_empty());
+        assert_eq!(bq.pop_front_bytes(1), None);
+    }
+
+    #[test]
+    fn starts_with_across_buffers() {
+        let mut bq = BufferQueue::new();
+        assert!(bq.starts_with(""));
+        assert!(!bq.starts_with("<"));
+        bq.push_back("<".to_tendril());
+        bq.push_back("!-".to_tendril());
+        bq.push_back("-x".to_tendril());
+        assert!(bq.starts_with("<!--"));
+        assert!(bq.starts_with("<!--x"));
+        assert!(!bq.starts_with("<!--xy"));
+        assert!(!bq.starts_with("<![CDATA["));
+        assert!(!bq.starts_with("<?"));
+        assert_eq!(bq.peek(), Some('<'));
+        assert_eq!(bq.next(), Some('<'));
+        assert!(bq.starts_with("!--"));
+    }
+
+    #[test]
+    fn pushed_back_input_comes_first() {
+        let mut bq = BufferQueue::new();
+        bq.push_back("def".to_tendril());
+        assert_eq!(bq.next(), Some('d'));
+        bq.push_front("".to_tendril());
+        bq.push_front("c".to_tendril());
+        bq.push_front("ab".to_tendril());
+        let mut out = String::new();
+        while let Some(c) = bq.next() {
+            out.push(c);
+        }
+        assert_eq!(out, "abcef");
+        assert!(bq.is_empty());
+    }
+
     #[test]
     fn can_eat() {
         // This is not very comprehensive.  We rely on the tokenizer
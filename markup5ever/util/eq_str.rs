@@ -0,0 +1,128 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Comparing atoms with string slices.
+
+use crate::QualName;
+use string_cache::{Atom, StaticAtomSet};
+
+/// Compares a value's string contents with a string slice, without
+/// interning the slice.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate markup5ever;
+/// # fn main() {
+/// use markup5ever::EqStr;
+///
+/// assert!(local_name!("div").eq_str("div"));
+/// assert!(local_name!("div").eq_str_ignore_ascii_case("DIV"));
+/// assert!(!None::<markup5ever::Prefix>.eq_str(""));
+/// # }
+/// ```
+pub trait EqStr {
+    /// The string contents to compare, or `None` if there are none. A value
+    /// without contents is not equal to any string.
+    fn str_contents(&self) -> Option<&str>;
+
+    /// Is the contents exactly `other`?
+    fn eq_str(&self, other: &str) -> bool {
+        self.str_contents() == Some(other)
+    }
+
+    /// Is the contents `other`, ignoring ASCII case?
+    fn eq_str_ignore_ascii_case(&self, other: &str) -> bool {
+        self.str_contents()
+            .map_or(false, |s| s.eq_ignore_ascii_case(other))
+    }
+
+    /// Does the contents start with `prefix`?
+    fn starts_with_str(&self, prefix: &str) -> bool {
+        self.str_contents().map_or(false, |s| s.starts_with(prefix))
+    }
+
+    /// Does the contents end with `suffix`?
+    fn ends_with_str(&self, suffix: &str) -> bool {
+        self.str_contents().map_or(false, |s| s.ends_with(suffix))
+    }
+}
+
+impl<Static: StaticAtomSet> EqStr for Atom<Static> {
+    fn str_contents(&self) -> Option<&str> {
+        Some(self)
+    }
+}
+
+impl<Static: StaticAtomSet> EqStr for Option<Atom<Static>> {
+    fn str_contents(&self) -> Option<&str> {
+        self.as_ref().map(|atom| &**atom)
+    }
+}
+
+/// Compares the local name.
+impl EqStr for QualName {
+    fn str_contents(&self) -> Option<&str> {
+        Some(&self.local)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EqStr;
+    use crate::{LocalName, Namespace, Prefix, QualName};
+
+    #[test]
+    fn atom() {
+        assert!(local_name!("div").eq_str("div"));
+        assert!(!local_name!("div").eq_str("DIV"));
+        assert!(local_name!("div").eq_str_ignore_ascii_case("DIV"));
+        assert!(LocalName::from("dIv").eq_str_ignore_ascii_case("div"));
+        assert!(!local_name!("div").eq_str_ignore_ascii_case("span"));
+    }
+
+    #[test]
+    fn option_atom() {
+        assert!(Some(namespace_prefix!("xlink")).eq_str("xlink"));
+        assert!(Some(namespace_prefix!("xlink")).eq_str_ignore_ascii_case("XLink"));
+        assert!(!None::<Prefix>.eq_str(""));
+        assert!(!None::<Prefix>.eq_str_ignore_ascii_case(""));
+    }
+
+    #[test]
+    fn prefix_and_namespace() {
+        assert!(namespace_prefix!("xmlns").eq_str("xmlns"));
+        assert!(!Prefix::from("xml").eq_str("xmlns"));
+        assert!(ns!(svg).eq_str("http://www.w3.org/2000/svg"));
+        assert!(Namespace::from("urn:X").eq_str_ignore_ascii_case("URN:x"));
+        assert!(!ns!().eq_str("http://www.w3.org/1999/xhtml"));
+    }
+
+    #[test]
+    fn qual_name() {
+        let name = QualName::new(
+            Some(namespace_prefix!("svg")),
+            ns!(svg),
+            local_name!("rect"),
+        );
+        assert!(name.eq_str("rect"));
+        assert!(!name.eq_str("svg:rect"));
+        assert!(name.eq_str_ignore_ascii_case("RECT"));
+    }
+
+    #[test]
+    fn starts_and_ends_with() {
+        assert!(LocalName::from("data-id").starts_with_str("data-"));
+        assert!(!local_name!("id").starts_with_str("data-"));
+        assert!(ns!(svg).ends_with_str("/svg"));
+        assert!(Some(namespace_prefix!("xlink")).starts_with_str("x"));
+        assert!(!None::<Prefix>.starts_with_str(""));
+        assert!(!None::<Prefix>.ends_with_str(""));
+    }
+}
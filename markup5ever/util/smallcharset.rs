@@ -11,31 +11,165 @@
 //!
 //! [`SmallCharSet`]: struct.SmallCharSet.html
 
+use std::fmt;
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Sub, SubAssign};
+
 /// Represents a set of "small characters", those with Unicode scalar
 /// values less than 64.
 ///
 /// This is stored as a bitmap, with 1 bit for each value.
-#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+#[derive(Eq, PartialEq, Clone, Copy, Hash)]
 pub struct SmallCharSet {
     pub bits: u64,
 }
 
 impl SmallCharSet {
-    /// Checks whether a character (u8 value below 64) is stored in the SmallCharSet.
+    /// Creates a set from its bitmap.
+    #[inline]
+    pub const fn from_bits(bits: u64) -> SmallCharSet {
+        SmallCharSet { bits }
+    }
+
+    /// Creates a set holding each of the given code points, which must all
+    /// be below 64. Unlike `small_char_set!`, this can be used in `const`
+    /// items with a slice defined elsewhere.
+    ///
+    /// # Panics
+    ///
+    /// If a code point is 64 or more. In a `const` item this is a compile
+    /// error instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate markup5ever;
+    /// # use markup5ever::SmallCharSet;
+    /// # fn main() {
+    /// const WS: SmallCharSet = SmallCharSet::from_codepoints(&[9, 10, 12, 13, 32]);
+    /// assert_eq!(WS, small_char_set!('\t' '\n' '\x0C' '\r' ' '));
+    /// # }
+    /// ```
+    pub const fn from_codepoints(cs: &[u8]) -> SmallCharSet {
+        let mut bits = 0;
+        let mut i = 0;
+        while i < cs.len() {
+            assert!(cs[i] < 64, "code point out of range for SmallCharSet");
+            bits |= 1 << cs[i];
+            i += 1;
+        }
+        SmallCharSet { bits }
+    }
+
+    /// Creates a set holding the characters of `s` with a code point below
+    /// 64, ignoring the others, e.g. for a set that is only known at run
+    /// time.
     ///
     /// # Examples
     ///
-    /// ```ignore
+    /// ```
+    /// # #[macro_use] extern crate markup5ever;
     /// # use markup5ever::SmallCharSet;
-    /// let set = SmallCharSet {
-    ///     bits: 0b00000000_01000000_00000100_00000000_00000000_00000000_00010000_00000000
-    /// };
-    /// assert!(set.contains(64));
-    /// assert!(set.contains(b'6')); // `b'6'` is the same as 64u8
+    /// # fn main() {
+    /// assert_eq!(SmallCharSet::from_chars("<&é"), small_char_set!('<' '&'));
+    /// # }
+    /// ```
+    pub const fn from_chars(s: &str) -> SmallCharSet {
+        // Bytes below 64 are always whole ASCII characters in UTF-8.
+        let bytes = s.as_bytes();
+        let mut bits = 0;
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] < 64 {
+                bits |= 1 << bytes[i];
+            }
+            i += 1;
+        }
+        SmallCharSet { bits }
+    }
+
+    /// Checks whether a character is stored in the SmallCharSet.
+    ///
+    /// Characters with a code point of 64 or above are never in the set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate markup5ever;
+    /// # fn main() {
+    /// let set = small_char_set!(48 49 50); // '0' '1' '2'
+    /// assert!(set.contains('1'));
+    /// assert!(!set.contains('3'));
+    /// assert!(!set.contains('😁'));
+    /// # }
+    /// ```
+    #[inline]
+    pub fn contains(&self, c: char) -> bool {
+        let n = c as u32;
+        n < 64 && 0 != (self.bits & (1 << n))
+    }
+
+    /// Returns the characters in the set, in ascending order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate markup5ever;
+    /// # fn main() {
+    /// let set = small_char_set!('<' '&' '>');
+    /// assert_eq!(set.iter().collect::<String>(), "&<>");
+    /// # }
     /// ```
+    pub fn iter(&self) -> impl Iterator<Item = char> {
+        let mut bits = self.bits;
+        std::iter::from_fn(move || {
+            if bits == 0 {
+                return None;
+            }
+            let n = bits.trailing_zeros();
+            bits &= bits - 1;
+            Some(char::from(n as u8))
+        })
+    }
+
+    /// Returns the number of characters in the set.
+    #[inline]
+    pub fn len(&self) -> u32 {
+        self.bits.count_ones()
+    }
+
+    /// Returns whether the set contains no characters.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+
+    /// Adds a character to the set. Returns whether it was not already
+    /// present. Characters with a code point of 64 or above are ignored.
+    pub fn insert(&mut self, c: char) -> bool {
+        if c as u32 >= 64 || self.contains(c) {
+            return false;
+        }
+        self.bits |= 1 << (c as u32);
+        true
+    }
+
+    /// Removes a character from the set. Returns whether it was present.
+    pub fn remove(&mut self, c: char) -> bool {
+        if !self.contains(c) {
+            return false;
+        }
+        self.bits &= !(1 << (c as u32));
+        true
+    }
+
+    /// Returns the set of code points below 64 that are not in this set.
+    ///
+    /// Like every `SmallCharSet`, the complement only covers code points
+    /// below 64: `contains` is false for anything above, so "any character
+    /// except these" rules have to handle those separately.
     #[inline]
-    fn contains(&self, n: u8) -> bool {
-        0 != (self.bits & (1 << (n as usize)))
+    pub const fn complement(&self) -> SmallCharSet {
+        SmallCharSet { bits: !self.bits }
     }
 
     /// Count the number of bytes of characters at the beginning of `buf` which are not in the set.
@@ -58,7 +192,7 @@ impl SmallCharSet {
     pub fn nonmember_prefix_len(&self, buf: &str) -> u32 {
         let mut n = 0;
         for b in buf.bytes() {
-            if b >= 64 || !self.contains(b) {
+            if !self.contains(b as char) {
                 n += 1;
             } else {
                 break;
@@ -66,10 +200,116 @@ impl SmallCharSet {
         }
         n
     }
+
+    /// Returns the byte index of the first character of `buf` which is in the set, if any.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate markup5ever;
+    /// # fn main() {
+    /// let set = small_char_set!('&' '<');
+    /// assert_eq!(set.find_in("a😁<b&"), Some(5));
+    /// assert_eq!(set.find_in("plain"), None);
+    /// # }
+    /// ```
+    #[inline]
+    pub fn find_in(&self, buf: &str) -> Option<usize> {
+        buf.bytes().position(|b| self.contains(b as char))
+    }
+}
+
+/// Lists the members, printable characters as char literals and others as
+/// `'\x##'`, followed by the raw `bits`.
+impl fmt::Debug for SmallCharSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SmallCharSet { ")?;
+        for c in self.iter() {
+            if c.is_ascii_graphic() || c == ' ' {
+                write!(f, "{:?}, ", c)?;
+            } else {
+                write!(f, "'\\x{:02X}', ", c as u32)?;
+            }
+        }
+        write!(f, "bits: {:#018x} }}", self.bits)
+    }
+}
+
+/// The union of two sets.
+impl BitOr for SmallCharSet {
+    type Output = SmallCharSet;
+
+    #[inline]
+    fn bitor(self, other: SmallCharSet) -> SmallCharSet {
+        SmallCharSet {
+            bits: self.bits | other.bits,
+        }
+    }
+}
+
+impl BitOrAssign for SmallCharSet {
+    #[inline]
+    fn bitor_assign(&mut self, other: SmallCharSet) {
+        self.bits |= other.bits;
+    }
+}
+
+/// The intersection of two sets.
+impl BitAnd for SmallCharSet {
+    type Output = SmallCharSet;
+
+    #[inline]
+    fn bitand(self, other: SmallCharSet) -> SmallCharSet {
+        SmallCharSet {
+            bits: self.bits & other.bits,
+        }
+    }
+}
+
+impl BitAndAssign for SmallCharSet {
+    #[inline]
+    fn bitand_assign(&mut self, other: SmallCharSet) {
+        self.bits &= other.bits;
+    }
+}
+
+/// The characters of the first set that are not in the second.
+impl Sub for SmallCharSet {
+    type Output = SmallCharSet;
+
+    #[inline]
+    fn sub(self, other: SmallCharSet) -> SmallCharSet {
+        SmallCharSet {
+            bits: self.bits & !other.bits,
+        }
+    }
+}
+
+impl SubAssign for SmallCharSet {
+    #[inline]
+    fn sub_assign(&mut self, other: SmallCharSet) {
+        self.bits &= !other.bits;
+    }
+}
+
+/// Serialized as the `bits` integer.
+#[cfg(feature = "serde")]
+impl serde::Serialize for SmallCharSet {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.bits)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for SmallCharSet {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u64::deserialize(deserializer).map(SmallCharSet::from_bits)
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use super::SmallCharSet;
     use std::iter::repeat;
 
     #[test]
@@ -87,4 +327,150 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn contains_ignores_large_code_points() {
+        let set = small_char_set!(0 63);
+        assert!(set.contains('\0'));
+        assert!(set.contains('?'));
+        assert!(!set.contains('@'));
+        assert!(!set.contains('\u{7F}'));
+        assert!(!set.contains('\u{10FFFF}'));
+    }
+
+    #[test]
+    fn iter_yields_members_in_order() {
+        let set = small_char_set!(12 54 42);
+        let members: Vec<u32> = set.iter().map(|c| c as u32).collect();
+        assert_eq!(members, [12, 42, 54]);
+        assert_eq!(
+            small_char_set!(0 63).iter().collect::<Vec<_>>(),
+            ['\0', '?']
+        );
+    }
+
+    #[test]
+    fn set_operations() {
+        let a = small_char_set!(1 2);
+        let b = small_char_set!(2 3);
+        assert_eq!((a | b).bits, small_char_set!(1 2 3).bits);
+        assert_eq!((a & b).bits, small_char_set!(2).bits);
+        assert_eq!((a - b).bits, small_char_set!(1).bits);
+
+        let mut c = a;
+        c |= b;
+        assert_eq!(c, a | b);
+        c &= b;
+        assert_eq!(c, b);
+        c -= a;
+        assert_eq!(c, small_char_set!(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "code point out of range")]
+    fn from_codepoints_rejects_out_of_range() {
+        let codepoints = vec![b'a', b'\t'];
+        SmallCharSet::from_codepoints(&codepoints);
+    }
+
+    #[test]
+    fn macro_accepts_chars_and_numbers() {
+        const WS: SmallCharSet = small_char_set!('\t' '\n' 12 '\r' ' ');
+        assert_eq!(WS.bits, small_char_set!(9 10 12 13 32).bits);
+        assert_eq!(small_char_set!(b'&' '<').bits, small_char_set!(38 60).bits);
+    }
+
+    #[test]
+    fn const_constructors() {
+        const CODEPOINTS: &[u8] = &[b'&', b'<', b'>'];
+        const FROM_CODEPOINTS: SmallCharSet = SmallCharSet::from_codepoints(CODEPOINTS);
+        const FROM_BITS: SmallCharSet = SmallCharSet::from_bits(FROM_CODEPOINTS.bits);
+        assert_eq!(FROM_CODEPOINTS, small_char_set!('&' '<' '>'));
+        assert_eq!(FROM_BITS, FROM_CODEPOINTS);
+        assert!(SmallCharSet::from_codepoints(&[]).is_empty());
+    }
+
+    #[test]
+    fn from_chars() {
+        let ws = SmallCharSet::from_chars(" \t\n\r\x0c");
+        assert_eq!(ws.bits, small_char_set!(' ' '\t' '\n' '\r' '\x0c').bits);
+        assert_eq!(SmallCharSet::from_chars("a\u{100}\u{2040}~").bits, 0);
+        assert!(SmallCharSet::from_chars("").is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        use serde_test::{assert_tokens, Token};
+
+        let set = small_char_set!('\t' '&' '<');
+        assert_tokens(&set, &[Token::U64(set.bits)]);
+    }
+
+    #[test]
+    fn complement() {
+        assert!(!small_char_set!(0).complement().contains('\u{0}'));
+        assert!(small_char_set!(0).complement().contains('\u{1}'));
+        assert!(!small_char_set!(0).complement().contains('é'));
+
+        let set = small_char_set!('&' '<');
+        assert_eq!(set.complement().len(), 62);
+        assert_eq!(set.complement().complement(), set);
+        assert!((set & set.complement()).is_empty());
+    }
+
+    #[test]
+    fn len_and_is_empty() {
+        assert_eq!(small_char_set!(0 63).len(), 2);
+        assert!(!small_char_set!(0 63).is_empty());
+        assert_eq!(SmallCharSet { bits: 0 }.len(), 0);
+        assert!(SmallCharSet { bits: 0 }.is_empty());
+    }
+
+    #[test]
+    fn debug_lists_members() {
+        assert_eq!(
+            format!("{:?}", small_char_set!('\t' ' ' '\'' '<')),
+            "SmallCharSet { '\\x09', ' ', '\\'', '<', bits: 0x1000008100000200 }"
+        );
+        assert_eq!(
+            format!("{:?}", SmallCharSet { bits: 0 }),
+            "SmallCharSet { bits: 0x0000000000000000 }"
+        );
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        let mut set = small_char_set!('&');
+        assert!(set.insert('<'));
+        assert!(!set.insert('<'));
+        assert!(!set.insert('é'));
+        assert_eq!(set, small_char_set!('&' '<'));
+
+        assert!(set.remove('&'));
+        assert!(!set.remove('&'));
+        assert!(!set.remove('é'));
+        assert_eq!(set, small_char_set!('<'));
+    }
+
+    #[test]
+    fn find_in_matches_manual_scan() {
+        let set = small_char_set!('&' '<' '>' '"');
+        for s in &[
+            "",
+            "abc",
+            "&",
+            "a<b",
+            "x>\"&",
+            "ü&é",
+            "😁\"",
+            "no specials here",
+        ] {
+            let manual = s
+                .char_indices()
+                .find(|&(_, c)| c == '&' || c == '<' || c == '>' || c == '"')
+                .map(|(i, _)| i);
+            assert_eq!(set.find_in(s), manual, "{:?}", s);
+        }
+    }
 }
@@ -0,0 +1,305 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Character classes from the [XML 1.0] specification.
+//!
+//! [XML 1.0]: https://www.w3.org/TR/xml/
+
+/// Is the character an ASCII alphanumeric character?
+pub fn is_ascii_alnum(c: char) -> bool {
+    matches!(c, '0'..='9' | 'a'..='z' | 'A'..='Z')
+}
+
+/// Can the character appear in an XML document at all? This is the
+/// [`Char`](https://www.w3.org/TR/xml/#NT-Char) production.
+pub fn is_xml_char(c: char) -> bool {
+    matches!(c,
+        '\u{9}' | '\u{A}' | '\u{D}' |
+        '\u{20}'..='\u{D7FF}' |
+        '\u{E000}'..='\u{FFFD}' |
+        '\u{10000}'..='\u{10FFFF}')
+}
+
+/// Can the character appear in an XML 1.1 document? This is the XML 1.1
+/// [`Char`](https://www.w3.org/TR/xml11/#NT-Char) production, which only
+/// leaves out NUL, surrogates, U+FFFE and U+FFFF. The characters accepted by
+/// [`is_restricted_char_11`] may only appear as character references.
+pub fn is_xml_char_11(c: char) -> bool {
+    matches!(c,
+        '\u{1}'..='\u{D7FF}' |
+        '\u{E000}'..='\u{FFFD}' |
+        '\u{10000}'..='\u{10FFFF}')
+}
+
+/// Must the character be written as a character reference in XML 1.1? This
+/// is the [`RestrictedChar`](https://www.w3.org/TR/xml11/#NT-RestrictedChar)
+/// production: the control characters other than tab, line feed, carriage
+/// return and NEL.
+pub fn is_restricted_char_11(c: char) -> bool {
+    matches!(c,
+        '\u{1}'..='\u{8}' |
+        '\u{B}'..='\u{C}' |
+        '\u{E}'..='\u{1F}' |
+        '\u{7F}'..='\u{84}' |
+        '\u{86}'..='\u{9F}')
+}
+
+/// Can the character start an XML name? This is the
+/// [`NameStartChar`](https://www.w3.org/TR/xml/#NT-NameStartChar) production.
+pub fn is_name_start_char(c: char) -> bool {
+    matches!(c,
+        ':' | 'A'..='Z' | '_' | 'a'..='z' |
+        '\u{C0}'..='\u{D6}' |
+        '\u{D8}'..='\u{F6}' |
+        '\u{F8}'..='\u{2FF}' |
+        '\u{370}'..='\u{37D}' |
+        '\u{37F}'..='\u{1FFF}' |
+        '\u{200C}'..='\u{200D}' |
+        '\u{2070}'..='\u{218F}' |
+        '\u{2C00}'..='\u{2FEF}' |
+        '\u{3001}'..='\u{D7FF}' |
+        '\u{F900}'..='\u{FDCF}' |
+        '\u{FDF0}'..='\u{FFFD}' |
+        '\u{10000}'..='\u{EFFFF}')
+}
+
+/// Can the character appear after the first character of an XML name? This
+/// is the [`NameChar`](https://www.w3.org/TR/xml/#NT-NameChar) production.
+pub fn is_name_char(c: char) -> bool {
+    is_name_start_char(c) ||
+        matches!(c,
+            '-' | '.' | '0'..='9' | '\u{B7}' |
+            '\u{300}'..='\u{36F}' |
+            '\u{203F}'..='\u{2040}')
+}
+
+/// Can the character start an XML 1.1 name? XML 1.0 has had the same name
+/// characters since its fifth edition, so this is [`is_name_start_char`].
+pub fn is_name_start_char_11(c: char) -> bool {
+    is_name_start_char(c)
+}
+
+/// Can the character appear after the first character of an XML 1.1 name?
+/// This is [`is_name_char`], for the same reason.
+pub fn is_name_char_11(c: char) -> bool {
+    is_name_char(c)
+}
+
+/// Can the character appear in a public identifier? This is the
+/// [`PubidChar`](https://www.w3.org/TR/xml/#NT-PubidChar) production.
+pub fn is_pubid_char(c: char) -> bool {
+    is_ascii_alnum(c) || " \r\n-'()+,./:=?;!*#@$_%".contains(c)
+}
+
+/// Is the string a [`Name`](https://www.w3.org/TR/xml/#NT-Name)?
+///
+/// ASCII characters, which make up nearly every name in practice, are looked
+/// up in a bitset; everything else goes through [`is_name_start_char`] and
+/// [`is_name_char`].
+///
+/// ```
+/// use markup5ever::xml_chars::is_valid_name;
+///
+/// assert!(is_valid_name("svg:rect"));
+/// assert!(!is_valid_name("1st"));
+/// assert!(!is_valid_name(""));
+/// ```
+pub fn is_valid_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if in_ascii_set(ASCII_NAME_START, c, is_name_start_char) => {
+            chars.all(|c| in_ascii_set(ASCII_NAME, c, is_name_char))
+        },
+        _ => false,
+    }
+}
+
+/// Is the string an [`NCName`](https://www.w3.org/TR/xml-names/#NT-NCName),
+/// a name without colons, as namespace prefixes and local names have to be?
+///
+/// ```
+/// use markup5ever::xml_chars::is_valid_ncname;
+///
+/// assert!(is_valid_ncname("rect"));
+/// assert!(!is_valid_ncname("svg:rect"));
+/// ```
+pub fn is_valid_ncname(s: &str) -> bool {
+    is_valid_name(s) && !s.contains(':')
+}
+
+const fn ascii_range(first: u8, last: u8) -> u128 {
+    ((1 << (last - first + 1)) - 1) << first
+}
+
+const fn ascii_bit(b: u8) -> u128 {
+    1 << b
+}
+
+const ASCII_NAME_START: u128 =
+    ascii_range(b'A', b'Z') | ascii_range(b'a', b'z') | ascii_bit(b':') | ascii_bit(b'_');
+
+const ASCII_NAME: u128 =
+    ASCII_NAME_START | ascii_range(b'0', b'9') | ascii_bit(b'-') | ascii_bit(b'.');
+
+#[inline]
+fn in_ascii_set(set: u128, c: char, slow: fn(char) -> bool) -> bool {
+    if c.is_ascii() {
+        set & (1 << c as u32) != 0
+    } else {
+        slow(c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_alnum() {
+        assert!(is_ascii_alnum('a'));
+        assert!(is_ascii_alnum('A'));
+        assert!(is_ascii_alnum('1'));
+        assert!(!is_ascii_alnum('!'));
+        assert!(!is_ascii_alnum('\u{a66e}'));
+    }
+
+    #[test]
+    fn xml_char() {
+        assert!(is_xml_char('\t'));
+        assert!(is_xml_char('a'));
+        assert!(is_xml_char('\u{10FFFF}'));
+        assert!(!is_xml_char('\0'));
+        assert!(!is_xml_char('\u{B}'));
+        assert!(!is_xml_char('\u{FFFE}'));
+    }
+
+    #[test]
+    fn xml_10_and_11_chars() {
+        // (character, XML 1.0 Char, XML 1.1 Char, XML 1.1 RestrictedChar)
+        let table = [
+            ('\0', false, false, false),
+            ('\u{1}', false, true, true),
+            ('\t', true, true, false),
+            ('\n', true, true, false),
+            ('\u{B}', false, true, true),
+            ('\r', true, true, false),
+            ('\u{1F}', false, true, true),
+            (' ', true, true, false),
+            ('a', true, true, false),
+            ('\u{7F}', true, true, true),
+            ('\u{85}', true, true, false),
+            ('\u{86}', true, true, true),
+            ('\u{9F}', true, true, true),
+            ('\u{A0}', true, true, false),
+            ('\u{2028}', true, true, false),
+            ('\u{FFFE}', false, false, false),
+            ('\u{10FFFF}', true, true, false),
+        ];
+        for &(c, xml_10, xml_11, restricted) in table.iter() {
+            assert_eq!(is_xml_char(c), xml_10, "{:?}", c);
+            assert_eq!(is_xml_char_11(c), xml_11, "{:?}", c);
+            assert_eq!(is_restricted_char_11(c), restricted, "{:?}", c);
+        }
+        for &c in ['a', ':', '_', '1', '-', '\u{B7}', '\u{E9}', '\u{D7}', ' '].iter() {
+            assert_eq!(is_name_start_char_11(c), is_name_start_char(c), "{:?}", c);
+            assert_eq!(is_name_char_11(c), is_name_char(c), "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn pubid_chars() {
+        for c in "-//W3C//DTD XHTML 1.0 Strict//EN".chars() {
+            assert!(is_pubid_char(c), "{:?}", c);
+        }
+        assert!(is_pubid_char('\''));
+        assert!(!is_pubid_char('"'));
+        assert!(!is_pubid_char('\t'));
+        assert!(!is_pubid_char('&'));
+        assert!(!is_pubid_char('\u{E9}'));
+    }
+
+    #[test]
+    fn name_chars() {
+        assert!(is_name_start_char('_'));
+        assert!(is_name_start_char(':'));
+        assert!(is_name_start_char('\u{E9}'));
+        assert!(!is_name_start_char('1'));
+        assert!(!is_name_start_char('-'));
+        assert!(!is_name_start_char('\u{B7}'));
+        assert!(is_name_char('1'));
+        assert!(is_name_char('-'));
+        assert!(is_name_char('\u{B7}'));
+        assert!(!is_name_char(' '));
+        assert!(!is_name_char('\u{D7}'));
+    }
+
+    #[test]
+    fn names_and_ncnames() {
+        for &(s, name, ncname) in [
+            ("", false, false),
+            ("1st", false, false),
+            ("-x", false, false),
+            ("svg:rect", true, false),
+            (":x", true, false),
+            ("x:", true, false),
+            ("rect", true, true),
+            ("_x.y-2", true, true),
+            ("données", true, true),
+            ("a b", false, false),
+        ]
+        .iter()
+        {
+            assert_eq!(is_valid_name(s), name, "{:?}", s);
+            assert_eq!(is_valid_ncname(s), ncname, "{:?}", s);
+        }
+    }
+
+    fn is_valid_name_per_char(s: &str) -> bool {
+        let mut chars = s.chars();
+        match chars.next() {
+            Some(c) if is_name_start_char(c) => chars.all(is_name_char),
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn xml_name_matches_per_char_check() {
+        for c in (0..0x250u32)
+            .chain(0x2000..0x2100)
+            .filter_map(std::char::from_u32)
+        {
+            for name in &[c.to_string(), format!("a{}", c), format!("{}a", c)] {
+                assert_eq!(
+                    is_valid_name(name),
+                    is_valid_name_per_char(name),
+                    "{:?}",
+                    name
+                );
+            }
+        }
+        for name in &[
+            "",
+            "a",
+            "svg:rect",
+            "xml-stylesheet",
+            "_x.1",
+            "1a",
+            "a b",
+            "é",
+            "a\u{300}",
+        ] {
+            assert_eq!(
+                is_valid_name(name),
+                is_valid_name_per_char(name),
+                "{:?}",
+                name
+            );
+        }
+    }
+}
@@ -480,7 +480,15 @@ impl Serialize for SerializableHandle {
                         }
                     },
 
-                    &NodeData::Doctype { ref name, .. } => serializer.write_doctype(&name)?,
+                    &NodeData::Doctype {
+                        ref name,
+                        ref public_id,
+                        ref system_id,
+                    } => serializer.write_doctype_full(
+                        &name,
+                        Some(&**public_id).filter(|id| !id.is_empty()),
+                        Some(&**system_id).filter(|id| !id.is_empty()),
+                    )?,
 
                     &NodeData::Text { ref contents } => {
                         serializer.write_text(&contents.borrow())?
@@ -19,4 +19,18 @@ else
     cargo test --all
 fi
 
+(cd markup5ever && cargo test --features serde)
+
+# trybuild compares compiler messages, which only stay put on stable.
+if [ $TRAVIS_RUST_VERSION = stable ]
+then
+    (cd ui-tests && cargo test)
+fi
+
+# tokio needs a newer compiler than the minimum supported one.
+if [ $TRAVIS_RUST_VERSION != 1.57.0 ]
+then
+    (cd xml5ever && cargo test --features tokio)
+fi
+
 cargo doc --all
@@ -0,0 +1,6 @@
+#[macro_use]
+extern crate markup5ever;
+
+fn main() {
+    let _ = small_char_set!('<' 64);
+}
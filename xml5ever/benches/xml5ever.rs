@@ -1,5 +1,6 @@
 #[macro_use]
 extern crate criterion;
+#[macro_use]
 extern crate markup5ever;
 extern crate xml5ever;
 
@@ -9,8 +10,11 @@ use std::path::PathBuf;
 use criterion::{black_box, Criterion};
 
 use markup5ever::buffer_queue::BufferQueue;
+use xml5ever::serialize::{SerializeOpts, Serializer, XmlSerializer};
 use xml5ever::tendril::*;
 use xml5ever::tokenizer::{Token, TokenSink, XmlTokenizer};
+use xml5ever::util::is_valid_name;
+use xml5ever::{Namespace, Prefix, QualName};
 
 struct Sink;
 
@@ -67,8 +71,82 @@ fn run_bench(c: &mut Criterion, name: &str) {
     });
 }
 
+fn name_validation_bench(c: &mut Criterion) {
+    let names = [
+        "html",
+        "body",
+        "div",
+        "svg:rect",
+        "xlink:href",
+        "xml-stylesheet",
+        "dc:creator",
+        "item_2",
+        "feed",
+        "entry",
+        "link",
+        "données",
+    ];
+    c.bench_function("xml name validation", move |b| {
+        b.iter(|| {
+            for name in names.iter() {
+                black_box(is_valid_name(black_box(name)));
+            }
+        })
+    });
+}
+
+fn attributes_bench(c: &mut Criterion, test_name: &str, namespaced: bool) {
+    let name = QualName::new(None, ns!(), local_name!("div"));
+    let attrs: Vec<(QualName, String)> = (0..60)
+        .map(|i| {
+            let local = format!("attr{}", i).into();
+            let name = if namespaced && i % 6 == 0 {
+                QualName::new(Some(Prefix::from("p")), Namespace::from("urn:p"), local)
+            } else {
+                QualName::new(None, ns!(), local)
+            };
+            (name, format!("value {}", i))
+        })
+        .collect();
+
+    c.bench_function(test_name, move |b| {
+        b.iter(|| {
+            let mut ser = XmlSerializer::new(Vec::with_capacity(4096), SerializeOpts::default());
+            for _ in 0..100 {
+                ser.start_elem(
+                    name.clone(),
+                    attrs.iter().map(|&(ref name, ref value)| (name, &**value)),
+                )
+                .unwrap();
+                ser.end_elem(name.clone()).unwrap();
+            }
+            black_box(ser.into_inner().unwrap());
+        })
+    });
+}
+
+fn text_escaping_bench(c: &mut Criterion) {
+    let name = QualName::new(None, ns!(), local_name!("p"));
+    let text = "Mostly plain prose, with the odd <tag> & entity in it. ".repeat(2000);
+
+    c.bench_function("xml serializing large text", move |b| {
+        b.iter(|| {
+            let mut ser =
+                XmlSerializer::new(Vec::with_capacity(128 * 1024), SerializeOpts::default());
+            ser.start_elem(name.clone(), None.into_iter()).unwrap();
+            ser.write_text(&text).unwrap();
+            ser.end_elem(name.clone()).unwrap();
+            black_box(ser.into_inner().unwrap());
+        })
+    });
+}
+
 fn xml5ever_benchmarks(c: &mut Criterion) {
     run_bench(c, "strong.xml");
+    name_validation_bench(c);
+    attributes_bench(c, "xml serializing 60 attributes", true);
+    attributes_bench(c, "xml serializing 60 plain attributes", false);
+    text_escaping_bench(c);
 }
 
 criterion_group!(benches, xml5ever_benchmarks);
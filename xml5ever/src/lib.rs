@@ -43,7 +43,8 @@ macro_rules! time {
     }};
 }
 
-mod util;
+/// XML character classes, shared with markup5ever.
+pub mod util;
 
 /// Driver
 pub mod driver;
@@ -0,0 +1,195 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::XmlSerializer;
+use super::{AttrRef, Serialize, SerializeOpts, Serializer, TraversalScope, XmlDeclaration};
+use crate::QualName;
+use std::io;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// The chunk size used by `AsyncXmlSerializer::new`.
+const DEFAULT_CHUNK_SIZE: usize = 8 * 1024;
+
+/// An XML serializer that writes to an asynchronous writer as it goes.
+///
+/// It is driven one event at a time, like [`XmlSerializer`], and does the
+/// same escaping and namespace handling: each call runs the synchronous
+/// serializer into a buffer, and once the buffer holds a full chunk, the
+/// whole chunks are written out with `.await`. So the output is written in
+/// chunks of exactly `chunk_size` bytes, except for the last one, and at
+/// most one chunk is held back between calls. A single call can still
+/// buffer more than that, for example a long text node or a subtree
+/// passed to `write_node`.
+///
+/// Only available with the `tokio` feature, which needs a newer Rust than
+/// the oldest one html5ever supports.
+///
+/// ```
+/// # #[macro_use] extern crate markup5ever;
+/// # extern crate xml5ever;
+/// use xml5ever::serialize::{AsyncXmlSerializer, SerializeOpts};
+/// use xml5ever::QualName;
+///
+/// # async fn write() -> std::io::Result<()> {
+/// let p = QualName::new(None, ns!(), local_name!("p"));
+/// let mut ser = AsyncXmlSerializer::new(Vec::new(), SerializeOpts::default());
+/// ser.start_elem(p.clone(), None.into_iter()).await?;
+/// ser.write_text("a < b").await?;
+/// ser.end_elem(p).await?;
+/// assert_eq!(ser.finish().await?, b"<p>a &lt; b</p>");
+/// # Ok(())
+/// # }
+/// # fn main() {}
+/// ```
+///
+/// [`XmlSerializer`]: struct.XmlSerializer.html
+pub struct AsyncXmlSerializer<Wr> {
+    inner: XmlSerializer<Vec<u8>>,
+    writer: Wr,
+    chunk_size: usize,
+}
+
+impl<Wr: AsyncWrite + Unpin> AsyncXmlSerializer<Wr> {
+    /// Creates a serializer that writes to `writer` in chunks of 8 KiB.
+    pub fn new(writer: Wr, opts: SerializeOpts) -> Self {
+        AsyncXmlSerializer::with_chunk_size(writer, opts, DEFAULT_CHUNK_SIZE)
+    }
+
+    /// Creates a serializer that writes to `writer` in chunks of
+    /// `chunk_size` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn with_chunk_size(writer: Wr, opts: SerializeOpts, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must not be zero");
+        AsyncXmlSerializer {
+            inner: XmlSerializer::new(Vec::with_capacity(chunk_size), opts),
+            writer: writer,
+            chunk_size: chunk_size,
+        }
+    }
+
+    /// The number of bytes serialized so far, including those that have
+    /// not been written out yet.
+    pub fn position(&self) -> usize {
+        self.inner.position()
+    }
+
+    /// Serializes the start of an element, like `Serializer::start_elem`.
+    pub async fn start_elem<'a, AttrIter>(
+        &mut self,
+        name: QualName,
+        attrs: AttrIter,
+    ) -> io::Result<()>
+    where
+        AttrIter: Iterator<Item = AttrRef<'a>>,
+    {
+        self.inner.start_elem(name, attrs)?;
+        self.write_chunks().await
+    }
+
+    /// Serializes the end of an element, like `Serializer::end_elem`.
+    pub async fn end_elem(&mut self, name: QualName) -> io::Result<()> {
+        self.inner.end_elem(name)?;
+        self.write_chunks().await
+    }
+
+    /// Serializes a text node, like `Serializer::write_text`.
+    pub async fn write_text(&mut self, text: &str) -> io::Result<()> {
+        self.inner.write_text(text)?;
+        self.write_chunks().await
+    }
+
+    /// Serializes a comment, like `Serializer::write_comment`.
+    pub async fn write_comment(&mut self, text: &str) -> io::Result<()> {
+        self.inner.write_comment(text)?;
+        self.write_chunks().await
+    }
+
+    /// Serializes a CDATA section, like `Serializer::write_cdata`.
+    pub async fn write_cdata(&mut self, text: &str) -> io::Result<()> {
+        self.inner.write_cdata(text)?;
+        self.write_chunks().await
+    }
+
+    /// Serializes a processing instruction, like
+    /// `Serializer::write_processing_instruction`.
+    pub async fn write_processing_instruction(
+        &mut self,
+        target: &str,
+        data: &str,
+    ) -> io::Result<()> {
+        self.inner.write_processing_instruction(target, data)?;
+        self.write_chunks().await
+    }
+
+    /// Serializes a DOCTYPE, like `Serializer::write_doctype_full`.
+    pub async fn write_doctype(
+        &mut self,
+        name: &str,
+        public_id: Option<&str>,
+        system_id: Option<&str>,
+    ) -> io::Result<()> {
+        self.inner.write_doctype_full(name, public_id, system_id)?;
+        self.write_chunks().await
+    }
+
+    /// Writes an XML declaration, like `XmlSerializer::write_xml_declaration`.
+    pub async fn write_xml_declaration(&mut self, decl: &XmlDeclaration) -> io::Result<()> {
+        self.inner.write_xml_declaration(decl)?;
+        self.write_chunks().await
+    }
+
+    /// Serializes `node` with the synchronous `Serialize` implementation.
+    ///
+    /// The whole subtree is serialized before any of it is written, so for
+    /// large documents it is better to call this for each child of the
+    /// root, or to drive the serializer one event at a time.
+    pub async fn write_node<T: Serialize>(
+        &mut self,
+        node: &T,
+        traversal_scope: TraversalScope,
+    ) -> io::Result<()> {
+        node.serialize(&mut self.inner, traversal_scope)?;
+        self.write_chunks().await
+    }
+
+    /// Writes out everything serialized so far, including text held back by
+    /// `coalesce_text`, and flushes the writer.
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        self.write_chunks_up_to(self.inner.writer.inner.len())
+            .await?;
+        self.writer.flush().await
+    }
+
+    /// Writes out everything serialized so far and returns the writer.
+    pub async fn finish(mut self) -> io::Result<Wr> {
+        self.flush().await?;
+        Ok(self.writer)
+    }
+
+    /// Writes out the whole chunks in the buffer, keeping the rest for the
+    /// next call.
+    async fn write_chunks(&mut self) -> io::Result<()> {
+        let len = self.inner.writer.inner.len();
+        self.write_chunks_up_to(len - len % self.chunk_size).await
+    }
+
+    /// Writes out the first `end` bytes of the buffer, a chunk at a time.
+    async fn write_chunks_up_to(&mut self, end: usize) -> io::Result<()> {
+        let buf = &mut self.inner.writer.inner;
+        for chunk in buf[..end].chunks(self.chunk_size) {
+            self.writer.write_all(chunk).await?;
+        }
+        buf.drain(..end);
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::QualName;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Reasons for the serializer to refuse to write a node.
+///
+/// The `Serializer` methods return `io::Result`, so these are handed out
+/// wrapped in an `io::Error` of kind `InvalidData`. The original value can
+/// be recovered with `io::Error::get_ref` and `downcast_ref`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SerializeError {
+    /// An element was started after the root element of a document was
+    /// closed.
+    MultipleRoots(QualName),
+    /// Text other than whitespace was written outside the root element of a
+    /// document.
+    TextOutsideRoot,
+    /// A DOCTYPE was written after the root element of a document was
+    /// started.
+    MisplacedDoctype,
+    /// Bytes handed to the serializer were not valid UTF-8. Holds the length
+    /// of the valid prefix.
+    InvalidUtf8(usize),
+    /// An element has two attributes with the same namespace and local name.
+    DuplicateAttribute(QualName),
+    /// An element in the null namespace would need an `xmlns=""`
+    /// declaration, which `allow_default_ns_undeclaration` forbids.
+    CannotUndeclareDefaultNamespace(QualName),
+    /// A character that XML does not allow anywhere in a document, such as
+    /// NUL, was found in text, a comment, markup declaration or attribute
+    /// value.
+    NotXmlChar {
+        /// The offending character.
+        character: char,
+        /// Its byte offset in the text or value.
+        index: usize,
+        /// The attribute whose value contains it, if any.
+        attribute: Option<QualName>,
+        /// The element it is in, or whose attribute has it. `None` at the
+        /// top level.
+        element: Option<QualName>,
+    },
+    /// The prefix or local part of an element name is not an NCName, an XML
+    /// name without colons.
+    InvalidName(QualName),
+    /// An element or attribute is missing from `allowed_elements` or
+    /// `allowed_attributes`.
+    DisallowedName(QualName),
+    /// Comment text contains `--` or ends with `-`.
+    InvalidComment,
+    /// A second DOCTYPE was written in a document.
+    MultipleDoctypes,
+    /// A DOCTYPE public identifier has a character other than a `PubidChar`,
+    /// or is given without a system identifier.
+    InvalidPublicId,
+    /// A DOCTYPE system identifier contains both kinds of quotes.
+    InvalidSystemId,
+    /// CDATA section text contains the `]]>` terminator.
+    InvalidCdata,
+    /// A processing instruction target is not an NCName, or is `xml` in
+    /// any case, which is reserved for the XML declaration.
+    InvalidPiTarget(String),
+    /// Processing instruction data contains the `?>` terminator.
+    InvalidPiData,
+    /// `generated_prefix_prefix` is not an NCName, ends in a digit or
+    /// starts with the reserved `xml`, so no valid prefix can be generated
+    /// from it.
+    InvalidGeneratedPrefix(String),
+    /// An element uses the `xmlns` prefix, which is reserved for namespace
+    /// declarations.
+    ReservedPrefix(QualName),
+    /// A markup declaration has an unterminated quote or unbalanced
+    /// brackets.
+    UnbalancedDeclaration,
+    /// An end tag does not match the innermost open element.
+    MismatchedEndTag {
+        /// The name of the innermost open element, if there is one.
+        expected: Option<QualName>,
+        /// The name given for the end tag.
+        found: QualName,
+    },
+}
+
+impl fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SerializeError::MultipleRoots(ref name) => write!(
+                f,
+                "element '{}' would be a second root element of the document",
+                name.local
+            ),
+            SerializeError::TextOutsideRoot => {
+                write!(f, "text outside the root element must be whitespace")
+            },
+            SerializeError::MisplacedDoctype => {
+                write!(f, "DOCTYPE must come before the root element")
+            },
+            SerializeError::InvalidUtf8(valid_up_to) => {
+                write!(f, "invalid UTF-8 after byte {}", valid_up_to)
+            },
+            SerializeError::DuplicateAttribute(ref name) => {
+                write!(f, "attribute '{}' is given more than once", name.expanded())
+            },
+            SerializeError::CannotUndeclareDefaultNamespace(ref name) => write!(
+                f,
+                "element '{}' would need to undeclare the default namespace",
+                name.local
+            ),
+            SerializeError::InvalidName(ref name) => {
+                write!(f, "'{}' is not a valid element name", Qualified(name))
+            },
+            SerializeError::DisallowedName(ref name) => {
+                write!(f, "'{}' is not allowed", name.expanded())
+            },
+            SerializeError::InvalidComment => {
+                write!(f, "comment text must not contain '--' or end with '-'")
+            },
+            SerializeError::MultipleDoctypes => {
+                write!(f, "a document can only have one DOCTYPE")
+            },
+            SerializeError::InvalidPublicId => write!(
+                f,
+                "DOCTYPE public identifier is invalid or has no system identifier"
+            ),
+            SerializeError::InvalidSystemId => write!(
+                f,
+                "DOCTYPE system identifier must not contain both kinds of quotes"
+            ),
+            SerializeError::InvalidCdata => {
+                write!(f, "CDATA section text must not contain ']]>'")
+            },
+            SerializeError::InvalidPiTarget(ref target) => write!(
+                f,
+                "'{}' is not a valid processing instruction target",
+                target
+            ),
+            SerializeError::InvalidPiData => {
+                write!(f, "processing instruction data must not contain '?>'")
+            },
+            SerializeError::InvalidGeneratedPrefix(ref start) => {
+                write!(f, "cannot generate namespace prefixes from '{}'", start)
+            },
+            SerializeError::ReservedPrefix(ref name) => write!(
+                f,
+                "element 'xmlns:{}' uses the reserved prefix 'xmlns'",
+                name.local
+            ),
+            SerializeError::UnbalancedDeclaration => write!(
+                f,
+                "markup declaration has an unterminated quote or unbalanced brackets"
+            ),
+            SerializeError::MismatchedEndTag {
+                ref expected,
+                ref found,
+            } => match *expected {
+                Some(ref expected) => write!(
+                    f,
+                    "end tag '{}' does not match the open element '{}'",
+                    Qualified(found),
+                    Qualified(expected)
+                ),
+                None => write!(f, "end tag '{}' has no open element", Qualified(found)),
+            },
+            SerializeError::NotXmlChar {
+                character,
+                index,
+                ref attribute,
+                ref element,
+            } => {
+                write!(
+                    f,
+                    "{:?} at {} is not allowed in an XML document",
+                    character, index
+                )?;
+                match (attribute, element) {
+                    (&Some(ref attr), &Some(ref elem)) => write!(
+                        f,
+                        " (in attribute '{}' of element '{}')",
+                        Qualified(attr),
+                        Qualified(elem)
+                    ),
+                    (&Some(ref attr), &None) => write!(f, " (in attribute '{}')", Qualified(attr)),
+                    (&None, &Some(ref elem)) => write!(f, " (in element '{}')", Qualified(elem)),
+                    (&None, &None) => Ok(()),
+                }
+            },
+        }
+    }
+}
+
+/// Displays a name the way it is written, as `prefix:local`.
+struct Qualified<'a>(&'a QualName);
+
+impl<'a> fmt::Display for Qualified<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.prefix {
+            Some(ref prefix) => write!(f, "{}:{}", prefix, self.0.local),
+            None => write!(f, "{}", self.0.local),
+        }
+    }
+}
+
+impl Error for SerializeError {}
+
+impl From<SerializeError> for io::Error {
+    fn from(err: SerializeError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
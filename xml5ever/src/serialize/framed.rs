@@ -0,0 +1,66 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+/// A writer that frames everything written to it with a length prefix, for
+/// embedding documents in binary protocols.
+///
+/// Output is buffered until `finish`, which writes the payload's length as
+/// a 4-byte big-endian integer to the inner writer, followed by the payload.
+///
+/// ```
+/// use xml5ever::serialize::FramedWriter;
+/// use std::io::Write;
+///
+/// let mut framed = FramedWriter::new(vec![]);
+/// framed.write_all(b"<a/>").unwrap();
+/// assert_eq!(framed.finish().unwrap(), b"\0\0\0\x04<a/>");
+/// ```
+#[derive(Debug)]
+pub struct FramedWriter<Wr> {
+    inner: Wr,
+    payload: Vec<u8>,
+}
+
+impl<Wr: Write> FramedWriter<Wr> {
+    /// Creates a framing writer that writes frames to `inner`.
+    pub fn new(inner: Wr) -> FramedWriter<Wr> {
+        FramedWriter {
+            inner: inner,
+            payload: vec![],
+        }
+    }
+
+    /// Writes the length prefix and the buffered payload to the inner
+    /// writer and returns it.
+    ///
+    /// Fails with `InvalidInput` if the payload does not fit a 4-byte
+    /// length, in which case nothing is written.
+    pub fn finish(mut self) -> io::Result<Wr> {
+        let len = u32::try_from(self.payload.len()).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "frame is longer than 4 GiB")
+        })?;
+        self.inner.write_all(&len.to_be_bytes())?;
+        self.inner.write_all(&self.payload)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<Wr> Write for FramedWriter<Wr> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.payload.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -7,22 +7,459 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use crate::interface::canonical_attribute_order;
+use crate::tendril::{ByteTendril, StrTendril};
 use crate::tree_builder::NamespaceMap;
-use crate::QualName;
+use crate::util::{
+    is_pubid_char, is_restricted_char_11, is_valid_ncname, is_xml_char, is_xml_char_11,
+};
+use crate::{Attribute, LocalName, Namespace, Prefix, QualName};
+use log::warn;
+use markup5ever::is_raw_text_element;
 pub use markup5ever::serialize::{AttrRef, Serialize, Serializer, TraversalScope};
+use markup5ever::{expanded_name, local_name, namespace_prefix, namespace_url, ns};
+use markup5ever::{small_char_set, SmallCharSet};
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::io::{self, Write};
+use std::str;
+use std::sync::Arc;
+
+#[cfg(feature = "tokio")]
+mod async_serializer;
+mod error;
+mod framed;
+mod uri;
+mod with_namespaces;
+
+#[cfg(feature = "tokio")]
+pub use self::async_serializer::AsyncXmlSerializer;
+pub use self::error::SerializeError;
+pub use self::framed::FramedWriter;
+pub use self::with_namespaces::WithNamespaces;
 
 #[derive(Clone)]
 /// Struct for setting serializer options.
 pub struct SerializeOpts {
     /// Serialize the root node? Default: ChildrenOnly
     pub traversal_scope: TraversalScope,
+
+    /// Hold back consecutive `write_text` calls and escape them in one pass
+    /// when the next non-text node (or the end of input) is reached, so that
+    /// fragmented text nodes are written as a single run. Default: false
+    pub coalesce_text: bool,
+
+    /// Return an error instead of writing output that is not well-formed
+    /// XML. Default: false
+    pub require_well_formed: bool,
+
+    /// What `require_well_formed` does with characters that XML does not
+    /// allow, like NUL, in text and attribute values. Default: Error
+    pub well_formed_policy: WellFormedPolicy,
+
+    /// Treat the top level of the serialized node as a whole document, with
+    /// a prolog, a single root element and an epilog. With
+    /// `require_well_formed` this rejects a second root element, a DOCTYPE
+    /// after the root and non-whitespace text outside the root. Leave this
+    /// off for fragments, which may have any number of top-level elements
+    /// and text nodes. Default: false
+    pub document_scope: bool,
+
+    /// How eagerly markup characters are escaped. Default: Strict
+    pub escape_level: EscapeLevel,
+
+    /// Write tabs, line feeds and carriage returns in attribute values as
+    /// character references (`&#x9;`, `&#xA;` and `&#xD;`). A parser
+    /// normalizes these characters to spaces when they appear literally, so
+    /// this is needed for attribute values to survive a round trip.
+    /// Default: false, true for `SerializeOpts::canonical`
+    pub escape_attr_whitespace: bool,
+
+    /// Write attributes in the order of Canonical XML, by namespace URI and
+    /// local name, rather than as given. Namespace declarations are always
+    /// written in that order. Default: false, true for
+    /// `SerializeOpts::canonical`
+    pub canonicalize: bool,
+
+    /// The quote around attribute values and namespace declarations. The
+    /// chosen quote is escaped in values, the other one is not.
+    /// Default: Double
+    pub quote_style: QuoteStyle,
+
+    /// Attributes whose values are URI references. When an element or one of
+    /// its ancestors has an `xml:base` attribute, relative references in these
+    /// attributes are written resolved against it. Prefixes are ignored when
+    /// matching names. Default: empty
+    pub resolve_relative_uris_for: HashSet<QualName>,
+
+    /// Whether an unprefixed element in the null namespace may be written
+    /// inside an element with a default namespace, which takes an `xmlns=""`
+    /// declaration. Some consumers reject those, so with this off such an
+    /// element is an error instead. Default: true
+    pub allow_default_ns_undeclaration: bool,
+
+    /// Called with the name and depth (0 for a top-level element) of every
+    /// element once its start tag has been written, e.g. for progress
+    /// reporting. Default: None
+    pub on_element: Option<ElementHook>,
+
+    /// Write a namespaced attribute with a prefix that is already bound to
+    /// its namespace, when there is one, rather than declaring its own
+    /// prefix or a generated `nsN` one. Default: false
+    pub minimize_attr_prefixes: bool,
+
+    /// The start of the prefixes generated for namespaced attributes
+    /// without one, which are this followed by a number, like `ns1`. It
+    /// has to be an NCName that does not end in a digit or start with
+    /// `xml`, which is reserved, or generating a prefix fails with
+    /// `SerializeError::InvalidGeneratedPrefix`. Default: "ns"
+    pub generated_prefix_prefix: String,
+
+    /// Unprefixed attributes in the null namespace that are written
+    /// according to `boolean_attribute_style`, like `disabled` or `checked`
+    /// in XHTML. Default: empty
+    pub boolean_attributes: HashSet<LocalName>,
+
+    /// How the attributes in `boolean_attributes` are written.
+    /// Default: Value
+    pub boolean_attribute_style: BooleanAttributeStyle,
+
+    /// The XML declaration written before the node by `serialize_document`,
+    /// and by `serialize` when `traversal_scope` is `IncludeNode`. Fragments
+    /// serialized with `ChildrenOnly` never get one. Default: None
+    pub xml_declaration: Option<XmlDeclaration>,
+
+    /// The DOCTYPE written by `serialize_document`, after the XML
+    /// declaration. Default: None
+    pub doctype: Option<DoctypeDeclaration>,
+
+    /// What to do with comment text that XML does not allow, because it
+    /// contains `--` or ends with `-`. Default: Error
+    pub comment_fallback: CommentFallback,
+
+    /// The same as `comment_fallback: CommentFallback::Split` when
+    /// `comment_fallback` is left at `Error`. Default: false
+    #[deprecated(note = "use `comment_fallback: CommentFallback::Split` instead")]
+    pub auto_fix_comments: bool,
+
+    /// Leave out comments. Default: false
+    pub strip_comments: bool,
+
+    /// Leave out processing instructions. Default: false
+    pub strip_processing_instructions: bool,
+
+    /// When set, only these elements may be written; any other is a
+    /// `SerializeError::DisallowedName`. Prefixes are ignored when matching
+    /// names. Default: None
+    pub allowed_elements: Option<HashSet<QualName>>,
+
+    /// Like `allowed_elements`, for attributes. Namespace declarations are
+    /// not subject to it. Default: None
+    pub allowed_attributes: Option<HashSet<QualName>>,
+
+    /// Write characters outside this set as numeric character references
+    /// (`&#xE9;`) in text and attribute values. Comments, processing
+    /// instructions and CDATA sections have no references, so they are left
+    /// alone. `Ascii` references everything above U+007F, and `NonControl`
+    /// only control characters. Default: None, for minimal escaping
+    pub reference_charset: Option<CharsetMask>,
+
+    /// Remove whitespace from the start and end of text nodes (of runs of
+    /// text with `coalesce_text`), dropping whitespace-only ones, except
+    /// where `xml:space="preserve"` is in effect. Default: false
+    pub trim_text: bool,
+
+    /// How to normalize whitespace in text nodes (in runs of text with
+    /// `coalesce_text`), except where `xml:space="preserve"` is in effect.
+    /// Applied after `trim_text`. Default: Preserve
+    pub text_whitespace: WhitespacePolicy,
+
+    /// The XML version whose characters are allowed. With `V1_1`, the
+    /// control characters XML 1.1 restricts are written as character
+    /// references in text and attribute values, and are an error in
+    /// comments and other literal text under `require_well_formed`.
+    /// Default: V1_0
+    pub xml_version: XmlVersion,
+
+    /// Put child elements, comments and processing instructions on lines
+    /// of their own, indented by their depth. Elements are left alone from
+    /// their first text onward, as is everything where `xml:space="preserve"`
+    /// is in effect, since whitespace is part of their content.
+    /// Default: None
+    pub indent: Option<IndentStyle>,
+
+    /// The line ending written by the serializer itself, e.g. for `indent`.
+    /// Line breaks in text are written as given. Default: Lf
+    pub newline: Newline,
+
+    /// Write the text of `script` and `style` elements in the HTML
+    /// namespace unescaped, as an HTML serializer does. The output is not
+    /// well-formed XML when such text has `<` or `&` in it, though
+    /// characters XML does not allow are still rejected under
+    /// `require_well_formed`. Default: false
+    pub raw_text_elements: bool,
+
+    /// Write Canonical XML 1.0: no XML declaration or DOCTYPE, CDATA
+    /// sections as escaped text, carriage returns in text as `&#xD;`, and
+    /// no namespace declaration that only repeats the binding already in
+    /// effect. At document scope, text outside the root element is left
+    /// out, and comments and processing instructions there are put on
+    /// lines of their own. `strip_comments` chooses between the variants
+    /// with and without comments. Use `SerializeOpts::canonical`, which
+    /// also sets up the escaping and attribute order this needs.
+    /// Default: false
+    pub c14n: bool,
+}
+
+/// A line ending.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Newline {
+    /// `\n`
+    Lf,
+    /// `\r\n`
+    CrLf,
+    /// `\r`
+    Cr,
+}
+
+/// An XML version, for `SerializeOpts::xml_version`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XmlVersion {
+    /// XML 1.0.
+    V1_0,
+    /// XML 1.1.
+    V1_1,
+}
+
+impl XmlVersion {
+    /// Is the character in the version's `Char` production?
+    fn is_char(self, c: char) -> bool {
+        match self {
+            XmlVersion::V1_0 => is_xml_char(c),
+            XmlVersion::V1_1 => is_xml_char_11(c),
+        }
+    }
+
+    /// Can the character only be written as a character reference?
+    fn is_restricted(self, c: char) -> bool {
+        self == XmlVersion::V1_1 && is_restricted_char_11(c)
+    }
+}
+
+/// The unit of indentation, written once per level of nesting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndentStyle {
+    /// The given number of spaces.
+    Spaces(usize),
+    /// One tab.
+    Tab,
+}
+
+/// How to write a comment whose text contains `--` or ends with `-`.
+///
+/// Turning such a comment into some other kind of node, like a CDATA
+/// section, is left to the caller, since it changes the document.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommentFallback {
+    /// Write the text as is, or fail with `SerializeError::InvalidComment`
+    /// under `require_well_formed`.
+    Error,
+    /// Put a space between hyphens (`a--b` becomes `a- -b`) and after a
+    /// trailing hyphen.
+    Split,
+    /// Remove every run of two or more hyphens and a trailing hyphen
+    /// (`a--b` becomes `ab`).
+    Strip,
+}
+
+/// What to do with a character that XML does not allow in text or an
+/// attribute value, under `require_well_formed`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WellFormedPolicy {
+    /// Fail with `SerializeError::NotXmlChar`.
+    Error,
+    /// Leave the character out.
+    Drop,
+    /// Write this character instead, usually U+FFFD REPLACEMENT CHARACTER.
+    ReplaceWith(char),
+}
+
+/// How `SerializeOpts::text_whitespace` normalizes whitespace in text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WhitespacePolicy {
+    /// Write the text as is.
+    Preserve,
+    /// Remove whitespace from the start and end, dropping whitespace-only
+    /// text, like `trim_text`.
+    Trim,
+    /// Replace every run of whitespace with a single space.
+    Collapse,
+}
+
+/// A set of characters for `SerializeOpts::reference_charset`.
+#[derive(Clone, Copy, Debug)]
+pub enum CharsetMask {
+    /// U+0000 to U+007F.
+    Ascii,
+    /// U+0000 to U+00FF.
+    Latin1,
+    /// The Basic Multilingual Plane, U+0000 to U+FFFF.
+    Bmp,
+    /// Everything but control characters (C0, DEL and C1), except for tab,
+    /// line feed and carriage return.
+    NonControl,
+    /// The characters for which the function returns true.
+    Custom(fn(char) -> bool),
+}
+
+impl CharsetMask {
+    /// Is the character in the set?
+    #[inline]
+    pub fn contains(self, c: char) -> bool {
+        match self {
+            CharsetMask::Ascii => c.is_ascii(),
+            CharsetMask::Latin1 => (c as u32) <= 0xFF,
+            CharsetMask::Bmp => (c as u32) <= 0xFFFF,
+            CharsetMask::NonControl => !c.is_control() || matches!(c, '\t' | '\n' | '\r'),
+            CharsetMask::Custom(f) => f(c),
+        }
+    }
+}
+
+/// How to write an attribute listed in `SerializeOpts::boolean_attributes`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BooleanAttributeStyle {
+    /// `disabled="value"`, with the attribute's own value, like any other
+    /// attribute.
+    Value,
+    /// `disabled=""`
+    Empty,
+    /// `disabled="disabled"`
+    Name,
+    /// `disabled`, as in HTML. This is not well-formed XML.
+    Minimized,
+}
+
+/// A callback for `SerializeOpts::on_element`.
+///
+/// It is shared rather than borrowed so that the options stay `Clone`; use
+/// atomics or a `Mutex` to collect results from it.
+pub type ElementHook = Arc<dyn Fn(&QualName, usize) + Send + Sync>;
+
+/// How eagerly the serializer escapes markup characters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EscapeLevel {
+    /// Escape `&`, `<` and `>` everywhere, plus the delimiting quote in
+    /// attribute values.
+    Strict,
+    /// Only escape what XML requires: `>` is left alone in attribute values.
+    /// Text content is escaped the same way as with `Strict`.
+    Minimal,
 }
 
+/// The contents of an XML declaration, `<?xml version="1.0"?>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XmlDeclaration {
+    /// The XML version. Default: "1.0"
+    pub version: String,
+    /// The `encoding` pseudo-attribute, omitted when None. Default: None
+    pub encoding: Option<String>,
+    /// The `standalone` pseudo-attribute. Since `no` is what a missing
+    /// `standalone` means anyway, `Some(false)` is only written with
+    /// `force_standalone`. Default: None
+    pub standalone: Option<bool>,
+    /// Write `standalone="no"` instead of leaving it out. Default: false
+    pub force_standalone: bool,
+}
+
+impl Default for XmlDeclaration {
+    fn default() -> XmlDeclaration {
+        XmlDeclaration {
+            version: "1.0".to_owned(),
+            encoding: None,
+            standalone: None,
+            force_standalone: false,
+        }
+    }
+}
+
+/// A DOCTYPE without an internal subset, `<!DOCTYPE name SYSTEM "uri">`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DoctypeDeclaration {
+    /// The name of the root element.
+    pub name: String,
+    /// The public identifier, which needs a system identifier as well.
+    pub public_id: Option<String>,
+    /// The system identifier.
+    pub system_id: Option<String>,
+}
+
+impl DoctypeDeclaration {
+    /// A DOCTYPE with only a name, `<!DOCTYPE name>`.
+    pub fn new(name: &str) -> DoctypeDeclaration {
+        DoctypeDeclaration {
+            name: name.to_owned(),
+            public_id: None,
+            system_id: None,
+        }
+    }
+}
+
+#[allow(deprecated)]
 impl Default for SerializeOpts {
     fn default() -> SerializeOpts {
         SerializeOpts {
             traversal_scope: TraversalScope::ChildrenOnly(None),
+            coalesce_text: false,
+            require_well_formed: false,
+            well_formed_policy: WellFormedPolicy::Error,
+            document_scope: false,
+            escape_level: EscapeLevel::Strict,
+            escape_attr_whitespace: false,
+            canonicalize: false,
+            quote_style: QuoteStyle::Double,
+            resolve_relative_uris_for: HashSet::new(),
+            allow_default_ns_undeclaration: true,
+            on_element: None,
+            minimize_attr_prefixes: false,
+            generated_prefix_prefix: "ns".to_owned(),
+            boolean_attributes: HashSet::new(),
+            boolean_attribute_style: BooleanAttributeStyle::Value,
+            xml_declaration: None,
+            doctype: None,
+            comment_fallback: CommentFallback::Error,
+            auto_fix_comments: false,
+            strip_comments: false,
+            strip_processing_instructions: false,
+            allowed_elements: None,
+            allowed_attributes: None,
+            reference_charset: None,
+            trim_text: false,
+            text_whitespace: WhitespacePolicy::Preserve,
+            xml_version: XmlVersion::V1_0,
+            indent: None,
+            newline: Newline::Lf,
+            raw_text_elements: false,
+            c14n: false,
+        }
+    }
+}
+
+impl SerializeOpts {
+    /// Options for canonical output, which differ from the defaults in that
+    ///
+    /// * `>` is not escaped in attribute values (`EscapeLevel::Minimal`),
+    /// * whitespace other than spaces in attribute values is escaped
+    ///   (`escape_attr_whitespace`),
+    /// * attributes are sorted (`canonicalize`),
+    /// * the rest of Canonical XML applies (`c14n`).
+    pub fn canonical() -> SerializeOpts {
+        SerializeOpts {
+            escape_level: EscapeLevel::Minimal,
+            escape_attr_whitespace: true,
+            c14n: true,
+            canonicalize: true,
+            ..Default::default()
         }
     }
 }
@@ -33,8 +470,146 @@ where
     Wr: Write,
     T: Serialize,
 {
-    let mut ser = XmlSerializer::new(writer);
-    node.serialize(&mut ser, opts.traversal_scope)
+    let mut ser = XmlSerializer::new(writer, opts.clone());
+    if let (Some(decl), TraversalScope::IncludeNode) =
+        (&opts.xml_declaration, &opts.traversal_scope)
+    {
+        ser.write_xml_declaration(decl)?;
+    }
+    node.serialize(&mut ser, opts.traversal_scope)?;
+    ser.flush()
+}
+
+/// Like [`serialize`], but borrows the writer, so that it can be used again
+/// afterwards, e.g. to write several fragments to one sink.
+///
+/// [`serialize`]: fn.serialize.html
+pub fn serialize_into<Wr, T>(writer: &mut Wr, node: &T, opts: SerializeOpts) -> io::Result<()>
+where
+    Wr: Write,
+    T: Serialize,
+{
+    serialize(writer, node, opts)
+}
+
+/// Escapes an attribute value the way `XmlSerializer` would write it between
+/// the given quotes, without the quotes themselves.
+///
+/// ```
+/// use xml5ever::serialize::{escape_attr_value, QuoteStyle};
+///
+/// let escaped = escape_attr_value("a \"b\" & 'c'", QuoteStyle::Single, &Default::default());
+/// assert_eq!(escaped.unwrap(), "a \"b\" &amp; &apos;c&apos;");
+/// ```
+pub fn escape_attr_value(
+    value: &str,
+    quote: QuoteStyle,
+    opts: &SerializeOpts,
+) -> io::Result<String> {
+    let mut out = Vec::with_capacity(value.len());
+    write_escaped(&mut out, value, EscapeContext::Attribute(quote), opts)?;
+    Ok(String::from_utf8(out).expect("serializer wrote invalid UTF-8"))
+}
+
+/// Serializes `node` into a `String`.
+///
+/// ```
+/// # #[macro_use] extern crate markup5ever;
+/// # extern crate xml5ever;
+/// use xml5ever::serialize::{serialize_to_string, Serialize, Serializer, TraversalScope};
+/// use xml5ever::QualName;
+/// # use std::io;
+///
+/// struct Empty;
+///
+/// impl Serialize for Empty {
+///     fn serialize<S: Serializer>(&self, ser: &mut S, _: TraversalScope) -> io::Result<()> {
+///         let name = QualName::new(None, ns!(), local_name!("br"));
+///         ser.start_elem(name.clone(), None.into_iter())?;
+///         ser.end_elem(name)
+///     }
+/// }
+///
+/// # fn main() {
+/// assert_eq!(serialize_to_string(&Empty, Default::default()).unwrap(), "<br></br>");
+/// # }
+/// ```
+pub fn serialize_to_string<T: Serialize>(node: &T, opts: SerializeOpts) -> io::Result<String> {
+    let mut out = Vec::new();
+    serialize(&mut out, node, opts)?;
+    String::from_utf8(out)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "serializer wrote invalid UTF-8"))
+}
+
+/// Serializes `node` into a tendril, the buffer type the tokenizer consumes,
+/// so the output can be parsed again without copying it.
+pub fn serialize_to_tendril<T: Serialize>(node: &T, opts: SerializeOpts) -> io::Result<StrTendril> {
+    let mut out = ByteTendril::new();
+    serialize(&mut out, node, opts)?;
+    out.try_reinterpret()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "serializer wrote invalid UTF-8"))
+}
+
+/// Serializes a whole document: the XML declaration and DOCTYPE from
+/// `opts`, if any, followed by `node`.
+///
+/// `document_scope` is always on, so with `require_well_formed` the output
+/// is checked to have a single root element. Use `traversal_scope` to say
+/// whether `node` is the root element itself (`IncludeNode`) or a document
+/// node whose children are to be written (`ChildrenOnly`). Fragments should
+/// go through [`serialize`] instead.
+///
+/// [`serialize`]: fn.serialize.html
+pub fn serialize_document<Wr, T>(writer: Wr, node: &T, opts: SerializeOpts) -> io::Result<()>
+where
+    Wr: Write,
+    T: Serialize,
+{
+    let opts = SerializeOpts {
+        document_scope: true,
+        ..opts
+    };
+    let mut ser = XmlSerializer::new(writer, opts.clone());
+    if let Some(ref decl) = opts.xml_declaration {
+        ser.write_xml_declaration(decl)?;
+    }
+    if let Some(ref doctype) = opts.doctype {
+        ser.write_doctype_full(
+            &doctype.name,
+            doctype.public_id.as_deref(),
+            doctype.system_id.as_deref(),
+        )?;
+    }
+    node.serialize(&mut ser, opts.traversal_scope)?;
+    ser.flush()
+}
+
+/// Serializes `children` inside a synthetic `root` element with the given
+/// attributes, e.g. to turn a fragment into a document.
+///
+/// Namespace declarations for `root` and its attributes are added as
+/// needed, and `children` are serialized in their scope, so they can use
+/// the prefixes declared on the root. `opts.traversal_scope` applies to
+/// `children`.
+pub fn serialize_wrapped<Wr, T>(
+    writer: Wr,
+    children: &T,
+    root: QualName,
+    root_attrs: &[Attribute],
+    opts: SerializeOpts,
+) -> io::Result<()>
+where
+    Wr: Write,
+    T: Serialize,
+{
+    let mut ser = XmlSerializer::new(writer, opts.clone());
+    ser.start_elem(
+        root.clone(),
+        root_attrs.iter().map(|attr| (&attr.name, &*attr.value)),
+    )?;
+    children.serialize(&mut ser, opts.traversal_scope)?;
+    ser.end_elem(root)?;
+    ser.flush()
 }
 
 /// Struct used for serializing nodes into a text that other XML
@@ -42,50 +617,268 @@ where
 ///
 /// Serializer contains a set of functions (start_elem, end_elem...)
 /// that make parsing nodes easier.
+///
+/// Besides being handed to `Serialize::serialize`, it can be driven one
+/// event at a time, e.g. for nodes that are generated as they are written:
+///
+/// * every `start_elem` is matched by an `end_elem` with the same name,
+///   with the calls for the element's content in between, so elements
+///   nest like the tree they describe;
+/// * text, comments and the like go to the innermost open element, or to
+///   the top level when there is none;
+/// * `flush` (or `into_inner`, which calls it) is called at the end, to
+///   write text held back by `coalesce_text`.
+///
+/// An `end_elem` without an open element is written as it is, with a
+/// warning logged.
+///
+/// ```
+/// # #[macro_use] extern crate markup5ever;
+/// # extern crate xml5ever;
+/// use xml5ever::serialize::{SerializeOpts, Serializer, XmlSerializer};
+/// use xml5ever::QualName;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let a = QualName::new(None, ns!(), local_name!("a"));
+/// let b = QualName::new(None, ns!(), local_name!("b"));
+/// let mut ser = XmlSerializer::new(Vec::new(), SerializeOpts::default());
+/// ser.start_elem(a.clone(), None.into_iter())?;
+/// ser.start_elem(b.clone(), None.into_iter())?;
+/// ser.write_text("text")?;
+/// ser.end_elem(b)?;
+/// ser.end_elem(a)?;
+/// assert_eq!(ser.into_inner()?, b"<a><b>text</b></a>");
+/// # Ok(())
+/// # }
+/// ```
 pub struct XmlSerializer<Wr> {
-    writer: Wr,
+    writer: CountingWriter<Wr>,
+    opts: SerializeOpts,
     namespace_stack: NamespaceMapStack,
+    pending_text: String,
+    phase: DocumentPhase,
+    /// Whether a DOCTYPE has been written.
+    seen_doctype: bool,
+    stack: Vec<ElemInfo>,
+}
+
+/// A writer that keeps track of how many bytes went through it.
+struct CountingWriter<Wr> {
+    inner: Wr,
+    written: usize,
 }
 
-#[derive(Debug)]
+impl<Wr: Write> Write for CountingWriter<Wr> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// What the serializer remembers about each open element.
+#[derive(Clone, Debug, Default)]
+struct ElemInfo {
+    /// The name of the element, for error messages. Scopes opened with
+    /// `push_namespace_scope` keep the name of the enclosing element.
+    name: Option<QualName>,
+    /// The base URI in effect, when relative URIs are being resolved.
+    base: Option<String>,
+    /// Whether `xml:space="preserve"` is in effect.
+    preserve_space: bool,
+    /// Whether whitespace must not be added to the content, because it has
+    /// text or space is preserved.
+    mixed: bool,
+    /// Whether a child was put on a line of its own, so the end tag has to
+    /// be too.
+    indented: bool,
+    /// Whether text is written unescaped, for `raw_text_elements`.
+    raw_text: bool,
+    /// Whether this is a scope opened with `push_namespace_scope` rather
+    /// than an element, so it has no end tag.
+    namespace_scope: bool,
+}
+
+/// Where the serializer is relative to the root element, at document scope.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DocumentPhase {
+    /// Before the root element.
+    Prolog,
+    /// Inside the root element.
+    Element,
+    /// After the root element was closed.
+    Epilog,
+}
+
+#[derive(Clone, Debug)]
 struct NamespaceMapStack(Vec<NamespaceMap>);
 
 impl NamespaceMapStack {
     fn new() -> NamespaceMapStack {
-        NamespaceMapStack(vec![])
+        NamespaceMapStack(vec![NamespaceMap::default()])
     }
 
     fn push(&mut self, namespace: NamespaceMap) {
         self.0.push(namespace);
     }
 
+    /// Closes the innermost scope. The base scope is never popped, so
+    /// `depth` cannot underflow on unbalanced input.
     fn pop(&mut self) {
-        self.0.pop();
+        if self.0.len() > 1 {
+            self.0.pop();
+        }
+    }
+
+    /// Number of elements currently open.
+    fn depth(&self) -> usize {
+        self.0.len() - 1
     }
 }
 
+/// Which quote character delimits an attribute value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuoteStyle {
+    /// `name="value"`
+    Double,
+    /// `name='value'`
+    Single,
+}
+
+/// The kind of markup a piece of text is written into, which decides how it
+/// has to be escaped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EscapeContext {
+    /// Element content. `&`, `<` and `>` are escaped.
+    Text,
+    /// An attribute value delimited by the given quote. `&`, `<`, `>` and
+    /// the delimiting quote are escaped, except for `>` with
+    /// `EscapeLevel::Minimal`.
+    Attribute(QuoteStyle),
+    /// The body of a comment. Written as is.
+    Comment,
+    /// The data of a processing instruction. Written as is.
+    ProcessingInstruction,
+    /// The content of a CDATA section. Written as is.
+    Cdata,
+}
+
 /// Writes given text into the Serializer, escaping it,
 /// depending on where the text is written inside the tag or attribute value.
 ///
 /// For example
 ///```text
 ///    <tag>'&-quotes'</tag>   becomes      <tag>'&amp;-quotes'</tag>
-///    <tag = "'&-quotes'">    becomes      <tag = "&apos;&amp;-quotes&apos;"
+///    <tag a="'&-quotes'">    becomes      <tag a="'&amp;-quotes'">
+///    <tag a=''&-quotes''>    becomes      <tag a='&apos;&amp;-quotes&apos;'>
 ///```
-fn write_to_buf_escaped<W: Write>(writer: &mut W, text: &str, attr_mode: bool) -> io::Result<()> {
-    for c in text.chars() {
+fn write_escaped<W: Write>(
+    writer: &mut W,
+    text: &str,
+    context: EscapeContext,
+    opts: &SerializeOpts,
+) -> io::Result<()> {
+    let set = match context {
+        EscapeContext::Text if opts.c14n => ESCAPE_TEXT | small_char_set!('\r'),
+        EscapeContext::Text => ESCAPE_TEXT,
+        EscapeContext::Attribute(quote) => {
+            let mut set = match quote {
+                QuoteStyle::Double => ESCAPE_ATTR,
+                QuoteStyle::Single => ESCAPE_ATTR_SINGLE,
+            };
+            if opts.escape_level == EscapeLevel::Minimal {
+                set -= small_char_set!('>');
+            }
+            if opts.escape_attr_whitespace {
+                set |= ESCAPE_ATTR_WHITESPACE;
+            }
+            set
+        },
+        EscapeContext::Comment | EscapeContext::ProcessingInstruction | EscapeContext::Cdata => {
+            return writer.write_all(text.as_bytes());
+        },
+    };
+    let mut rest = text;
+    while let Some(i) = next_escape(rest, set, opts) {
+        writer.write_all(rest[..i].as_bytes())?;
+        let c = rest[i..].chars().next().unwrap();
         match c {
             '&' => writer.write_all(b"&amp;"),
-            '\'' if attr_mode => writer.write_all(b"&apos;"),
-            '"' if attr_mode => writer.write_all(b"&quot;"),
-            '<' if !attr_mode => writer.write_all(b"&lt;"),
-            '>' if !attr_mode => writer.write_all(b"&gt;"),
-            c => writer.write_fmt(format_args!("{}", c)),
+            '<' => writer.write_all(b"&lt;"),
+            '>' => writer.write_all(b"&gt;"),
+            '"' => writer.write_all(b"&quot;"),
+            '\'' => writer.write_all(b"&apos;"),
+            c => write!(writer, "&#x{:X};", c as u32),
         }?;
+        rest = &rest[i + c.len_utf8()..];
     }
-    Ok(())
+    writer.write_all(rest.as_bytes())
+}
+
+/// Writes `=` and the attribute value, escaped and in the quotes chosen in
+/// `opts`.
+fn write_attr_value<W: Write>(writer: &mut W, value: &str, opts: &SerializeOpts) -> io::Result<()> {
+    let quote: &[u8] = match opts.quote_style {
+        QuoteStyle::Double => b"\"",
+        QuoteStyle::Single => b"'",
+    };
+    writer.write_all(b"=")?;
+    writer.write_all(quote)?;
+    write_escaped(
+        writer,
+        value,
+        EscapeContext::Attribute(opts.quote_style),
+        opts,
+    )?;
+    writer.write_all(quote)
+}
+
+/// Writes a DOCTYPE public or system identifier in double quotes, or in
+/// single quotes if it contains a double quote. Identifiers have no
+/// escapes.
+fn write_doctype_literal<W: Write>(writer: &mut W, literal: &str) -> io::Result<()> {
+    let quote: &[u8] = if literal.contains('"') { b"'" } else { b"\"" };
+    writer.write_all(quote)?;
+    writer.write_all(literal.as_bytes())?;
+    writer.write_all(quote)
 }
 
+/// Finds the next character in `text` that `write_escaped` has to replace:
+/// one from `set`, one outside `reference_charset`, or one that the XML
+/// version restricts.
+#[inline]
+fn next_escape(text: &str, set: SmallCharSet, opts: &SerializeOpts) -> Option<usize> {
+    let version = opts.xml_version;
+    match opts.reference_charset {
+        None if version == XmlVersion::V1_0 => set.find_in(text),
+        mask => text
+            .char_indices()
+            .find(|&(_, c)| {
+                set.contains(c) || !mask.map_or(true, |m| m.contains(c)) || version.is_restricted(c)
+            })
+            .map(|(i, _)| i),
+    }
+}
+
+/// Characters escaped in text content.
+const ESCAPE_TEXT: SmallCharSet = small_char_set!('&' '<' '>');
+
+/// Characters escaped in a double-quoted attribute value with
+/// `EscapeLevel::Strict`.
+const ESCAPE_ATTR: SmallCharSet = small_char_set!('&' '<' '>' '"');
+
+/// Characters escaped in a single-quoted attribute value with
+/// `EscapeLevel::Strict`.
+const ESCAPE_ATTR_SINGLE: SmallCharSet = small_char_set!('&' '<' '>' '\'');
+
+/// Characters additionally escaped in attribute values with
+/// `escape_attr_whitespace`.
+const ESCAPE_ATTR_WHITESPACE: SmallCharSet = small_char_set!('\t' '\n' '\r');
+
 #[inline]
 fn write_qual_name<W: Write>(writer: &mut W, name: &QualName) -> io::Result<()> {
     if let Some(ref prefix) = name.prefix {
@@ -99,122 +892,3210 @@ fn write_qual_name<W: Write>(writer: &mut W, name: &QualName) -> io::Result<()>
     Ok(())
 }
 
+/// The `xmlns` prefix is reserved for namespace declarations, and binding
+/// it the way any other element prefix would be bound is an error for XML
+/// parsers. An element that uses it anyway is written without a prefix,
+/// in its namespace.
+fn without_xmlns_prefix(name: QualName) -> QualName {
+    if name.prefix == Some(namespace_prefix!("xmlns")) {
+        QualName::new(None, name.ns, name.local)
+    } else {
+        name
+    }
+}
+
+/// Makes comment text acceptable to XML as `comment_fallback` says.
+fn fix_comment(text: &str, fallback: CommentFallback) -> Cow<'_, str> {
+    if fallback == CommentFallback::Error || (!text.contains("--") && !text.ends_with('-')) {
+        return Cow::Borrowed(text);
+    }
+    let mut fixed = String::with_capacity(text.len() + 2);
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match fallback {
+            CommentFallback::Split if c == '-' && fixed.ends_with('-') => fixed.push(' '),
+            CommentFallback::Strip if c == '-' && chars.peek() == Some(&'-') => {
+                while chars.peek() == Some(&'-') {
+                    chars.next();
+                }
+                continue;
+            },
+            _ => {},
+        }
+        fixed.push(c);
+    }
+    if fixed.ends_with('-') {
+        match fallback {
+            CommentFallback::Strip => {
+                fixed.pop();
+            },
+            _ => fixed.push(' '),
+        }
+    }
+    Cow::Owned(fixed)
+}
+
+/// Applies `well_formed_policy` to the characters of `text` that XML does
+/// not allow, when it is not `Error`.
+fn fix_xml_chars<'t>(text: &'t str, opts: &SerializeOpts) -> Cow<'t, str> {
+    let version = opts.xml_version;
+    let fixing = opts.require_well_formed && opts.well_formed_policy != WellFormedPolicy::Error;
+    if !fixing || text.chars().all(|c| version.is_char(c)) {
+        return Cow::Borrowed(text);
+    }
+    match opts.well_formed_policy {
+        WellFormedPolicy::Error => unreachable!(),
+        WellFormedPolicy::Drop => {
+            Cow::Owned(text.chars().filter(|&c| version.is_char(c)).collect())
+        },
+        WellFormedPolicy::ReplaceWith(replacement) => Cow::Owned(
+            text.chars()
+                .map(|c| if version.is_char(c) { c } else { replacement })
+                .collect(),
+        ),
+    }
+}
+
+/// Checks a name against `allowed_elements` or `allowed_attributes`.
+fn is_allowed(allowed: &Option<HashSet<QualName>>, name: &QualName) -> bool {
+    match *allowed {
+        Some(ref allowed) => allowed
+            .iter()
+            .any(|allowed| allowed.expanded() == name.expanded()),
+        None => true,
+    }
+}
+
+/// Fails with `SerializeError::NotXmlChar` at the first character of `text`
+/// that `version` does not allow, naming `attribute` if the text is its
+/// value. Text that is written literally, rather than escaped, must not have
+/// restricted characters either.
+fn check_xml_chars(
+    text: &str,
+    element: Option<&QualName>,
+    attribute: Option<&QualName>,
+    version: XmlVersion,
+    literal: bool,
+) -> Result<(), SerializeError> {
+    match text
+        .char_indices()
+        .find(|&(_, c)| !version.is_char(c) || (literal && version.is_restricted(c)))
+    {
+        Some((index, character)) => Err(SerializeError::NotXmlChar {
+            character: character,
+            index: index,
+            attribute: attribute.cloned(),
+            element: element.cloned(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// The cheap checks done by `write_markup_declaration`: every character is
+/// allowed in XML, quotes are closed, and `[`, `]` and `<`, `>` outside
+/// quotes are balanced.
+fn check_markup_declaration(text: &str, version: XmlVersion) -> Result<(), SerializeError> {
+    let mut quote = None;
+    let mut brackets = 0usize;
+    let mut angles = 0usize;
+    check_xml_chars(text, None, None, version, true)?;
+    for c in text.chars() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {},
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '[') => brackets += 1,
+            (None, '<') => angles += 1,
+            (None, ']') if brackets > 0 => brackets -= 1,
+            (None, '>') if angles > 0 => angles -= 1,
+            (None, ']') | (None, '>') => return Err(SerializeError::UnbalancedDeclaration),
+            (None, _) => {},
+        }
+    }
+    if quote.is_some() || brackets > 0 || angles > 0 {
+        return Err(SerializeError::UnbalancedDeclaration);
+    }
+    Ok(())
+}
+
 impl<Wr: Write> XmlSerializer<Wr> {
     /// Creates a new Serializier from a writer and given serialization options.
-    pub fn new(writer: Wr) -> Self {
+    pub fn new(writer: Wr, opts: SerializeOpts) -> Self {
         XmlSerializer {
-            writer: writer,
+            writer: CountingWriter {
+                inner: writer,
+                written: 0,
+            },
+            opts: opts,
             namespace_stack: NamespaceMapStack::new(),
+            pending_text: String::new(),
+            phase: DocumentPhase::Prolog,
+            seen_doctype: false,
+            stack: vec![],
         }
     }
 
-    #[inline(always)]
-    fn qual_name(&mut self, name: &QualName) -> io::Result<()> {
-        self.find_or_insert_ns(name);
-        write_qual_name(&mut self.writer, name)
+    /// Writes out any text held back by `coalesce_text` and returns the
+    /// underlying writer.
+    pub fn into_inner(mut self) -> io::Result<Wr> {
+        self.flush_text()?;
+        Ok(self.writer.inner)
     }
 
-    #[inline(always)]
-    fn qual_attr_name(&mut self, name: &QualName) -> io::Result<()> {
-        self.find_or_insert_ns(name);
-        write_qual_name(&mut self.writer, name)
+    /// The number of bytes written so far. Text held back by
+    /// `coalesce_text` only counts once it is flushed.
+    pub fn position(&self) -> usize {
+        self.writer.written
     }
 
-    fn find_uri(&self, name: &QualName) -> bool {
-        let mut found = false;
-        for stack in self.namespace_stack.0.iter().rev() {
-            if let Some(&Some(ref el)) = stack.get(&name.prefix) {
-                found = *el == name.ns;
-                break;
-            }
-        }
-        found
+    /// Writes `text` to the output, escaped as required by `context`.
+    pub fn escape(&mut self, text: &str, context: EscapeContext) -> io::Result<()> {
+        write_escaped(&mut self.writer, text, context, &self.opts)
     }
 
-    fn find_or_insert_ns(&mut self, name: &QualName) {
-        if name.prefix.is_some() || &*name.ns != "" {
-            if !self.find_uri(name) {
-                if let Some(last_ns) = self.namespace_stack.0.last_mut() {
-                    last_ns.insert(name);
-                }
-            }
+    /// Serializes text given as bytes, like `write_text`.
+    ///
+    /// The serializer only ever emits UTF-8, so the bytes are validated first
+    /// and `SerializeError::InvalidUtf8` is returned, with nothing written,
+    /// if they are not valid UTF-8. There is no unchecked variant.
+    pub fn write_text_bytes(&mut self, text: &[u8]) -> io::Result<()> {
+        match str::from_utf8(text) {
+            Ok(text) => self.write_text(text),
+            Err(err) => Err(SerializeError::InvalidUtf8(err.valid_up_to()).into()),
         }
     }
-}
 
-impl<Wr: Write> Serializer for XmlSerializer<Wr> {
-    /// Serializes given start element into text. Start element contains
-    /// qualified name and an attributes iterator.
-    fn start_elem<'a, AttrIter>(&mut self, name: QualName, attrs: AttrIter) -> io::Result<()>
-    where
-        AttrIter: Iterator<Item = AttrRef<'a>>,
-    {
-        self.namespace_stack.push(NamespaceMap::empty());
+    /// Writes an XML declaration. It has to come before anything else in
+    /// the document, which is up to the caller.
+    pub fn write_xml_declaration(&mut self, decl: &XmlDeclaration) -> io::Result<()> {
+        if self.opts.c14n {
+            return Ok(());
+        }
+        self.writer.write_all(b"<?xml version=\"")?;
+        self.writer.write_all(decl.version.as_bytes())?;
+        self.writer.write_all(b"\"")?;
+        if let Some(ref encoding) = decl.encoding {
+            self.writer.write_all(b" encoding=\"")?;
+            self.writer.write_all(encoding.as_bytes())?;
+            self.writer.write_all(b"\"")?;
+        }
+        match decl.standalone {
+            Some(true) => self.writer.write_all(b" standalone=\"yes\"")?,
+            Some(false) if decl.force_standalone => self.writer.write_all(b" standalone=\"no\"")?,
+            _ => {},
+        }
+        self.writer.write_all(b"?>")
+    }
 
-        self.writer.write_all(b"<")?;
-        self.qual_name(&name)?;
-        if let Some(current_namespace) = self.namespace_stack.0.last() {
-            for (prefix, url_opt) in current_namespace.get_scope_iter() {
-                self.writer.write_all(b" xmlns")?;
-                if let &Some(ref p) = prefix {
-                    self.writer.write_all(b":")?;
-                    self.writer.write_all(&*p.as_bytes())?;
-                }
+    /// Puts the next child of the current element on a new line, if
+    /// indenting and the element has no text.
+    fn indent_child(&mut self) -> io::Result<()> {
+        if self.opts.indent.is_none() {
+            return Ok(());
+        }
+        match self.stack.last_mut() {
+            Some(info) if !info.mixed => info.indented = true,
+            _ => return Ok(()),
+        }
+        self.write_indent(self.namespace_stack.depth())
+    }
 
-                self.writer.write_all(b"=\"")?;
-                let url = if let &Some(ref a) = url_opt {
-                    a.as_bytes()
-                } else {
-                    b""
-                };
-                self.writer.write_all(url)?;
-                self.writer.write_all(b"\"")?;
-            }
+    /// Writes a newline followed by `depth` indentation units.
+    fn write_indent(&mut self, depth: usize) -> io::Result<()> {
+        let (fill, width) = match self.opts.indent {
+            Some(IndentStyle::Spaces(n)) => (b' ', n),
+            Some(IndentStyle::Tab) => (b'\t', 1),
+            None => return Ok(()),
+        };
+        self.write_newline()?;
+        for _ in 0..width * depth {
+            self.writer.write_all(&[fill])?;
         }
-        for (name, value) in attrs {
-            self.writer.write_all(b" ")?;
-            self.qual_attr_name(&name)?;
-            self.writer.write_all(b"=\"")?;
-            write_to_buf_escaped(&mut self.writer, value, true)?;
-            self.writer.write_all(b"\"")?;
+        Ok(())
+    }
+
+    /// Writes the line ending chosen in the options.
+    fn write_newline(&mut self) -> io::Result<()> {
+        self.writer.write_all(match self.opts.newline {
+            Newline::Lf => b"\n",
+            Newline::CrLf => b"\r\n",
+            Newline::Cr => b"\r",
+        })
+    }
+
+    /// Records that the current element has text, so no more whitespace is
+    /// added to it.
+    fn mark_mixed(&mut self) {
+        if let Some(info) = self.stack.last_mut() {
+            info.mixed = true;
         }
-        self.writer.write_all(b">")?;
+    }
+
+    /// Opens a namespace scope with the given bindings without writing
+    /// anything, as if the following nodes were inside an element that
+    /// declares them. Elements and attributes using these bindings are
+    /// written without declaring them again.
+    ///
+    /// The scope counts as an open element, e.g. for the depth passed to
+    /// `on_element`, and has to be closed with `pop_namespace_scope`.
+    pub fn push_namespace_scope(
+        &mut self,
+        decls: &[(Option<Prefix>, Namespace)],
+    ) -> io::Result<()> {
+        self.flush_text()?;
+        let scope = NamespaceMap::from_declarations(decls.iter().cloned());
+        let info = self.stack.last().cloned().unwrap_or_default();
+        self.stack.push(ElemInfo {
+            namespace_scope: true,
+            ..info
+        });
+        self.namespace_stack.push(scope);
         Ok(())
     }
 
-    /// Serializes given end element into text.
-    fn end_elem(&mut self, name: QualName) -> io::Result<()> {
+    /// Closes the scope opened by the matching `push_namespace_scope`.
+    pub fn pop_namespace_scope(&mut self) -> io::Result<()> {
+        self.flush_text()?;
+        self.stack.pop();
         self.namespace_stack.pop();
-        self.writer.write_all(b"</")?;
-        self.qual_name(&name)?;
-        self.writer.write_all(b">")
+        Ok(())
     }
 
-    /// Serializes comment into text.
-    fn write_comment(&mut self, text: &str) -> io::Result<()> {
-        self.writer.write_all(b"<!--")?;
+    /// Writes `<!` + `text` + `>` verbatim, for markup declarations such
+    /// as `<!ENTITY ...>` or a DOCTYPE with an internal subset, which
+    /// `write_doctype` cannot express.
+    ///
+    /// This is an escape hatch: the text is not parsed, so nothing stops it
+    /// from producing a broken document. With `require_well_formed` it is
+    /// only checked for characters XML does not allow
+    /// (`SerializeError::NotXmlChar`) and for unterminated quotes or
+    /// unbalanced brackets (`SerializeError::UnbalancedDeclaration`).
+    pub fn write_markup_declaration(&mut self, text: &str) -> io::Result<()> {
+        self.flush_text()?;
+        if self.opts.require_well_formed {
+            check_markup_declaration(text, self.opts.xml_version)?;
+        }
+        self.writer.write_all(b"<!")?;
         self.writer.write_all(text.as_bytes())?;
-        self.writer.write_all(b"-->")
+        self.writer.write_all(b">")
     }
 
-    /// Serializes given doctype
-    fn write_doctype(&mut self, name: &str) -> io::Result<()> {
-        self.writer.write_all(b"<!DOCTYPE ")?;
-        self.writer.write_all(name.as_bytes())?;
-        self.writer.write_all(b">")
+    /// Returns the start tag `start_elem` would write for this element at
+    /// the current position, with its attributes sorted by namespace and
+    /// local name, without writing anything.
+    ///
+    /// Namespace declarations needed by the element are included, resolved
+    /// against the declarations currently in scope. `on_element` is not
+    /// called for it. Two elements whose
+    /// attributes differ only in order get the same signature, which makes
+    /// it usable as a cache key for rendered subtrees.
+    pub fn element_signature(&self, name: &QualName, attrs: &[Attribute]) -> io::Result<String> {
+        let mut sorted: Vec<&Attribute> = attrs.iter().collect();
+        sorted.sort_by(|a, b| (&*a.name.ns, &*a.name.local).cmp(&(&*b.name.ns, &*b.name.local)));
+
+        let mut ser = XmlSerializer {
+            writer: CountingWriter {
+                inner: Vec::new(),
+                written: 0,
+            },
+            opts: SerializeOpts {
+                indent: None,
+                on_element: None,
+                ..self.opts.clone()
+            },
+            namespace_stack: self.namespace_stack.clone(),
+            pending_text: String::new(),
+            phase: DocumentPhase::Element,
+            seen_doctype: self.seen_doctype,
+            stack: self.stack.clone(),
+        };
+        ser.start_elem(
+            name.clone(),
+            sorted.into_iter().map(|attr| (&attr.name, &*attr.value)),
+        )?;
+        Ok(String::from_utf8(ser.writer.inner).expect("serializer wrote invalid UTF-8"))
     }
 
-    /// Serializes text for a node or an attributes.
-    fn write_text(&mut self, text: &str) -> io::Result<()> {
-        write_to_buf_escaped(&mut self.writer, text, false)
+    /// Works out the base URI for an element from its parent's and its own
+    /// `xml:base` attribute. This is only tracked when there are attributes
+    /// to resolve.
+    fn element_base(&self, attrs: &[AttrRef]) -> Option<String> {
+        if self.opts.resolve_relative_uris_for.is_empty() {
+            return None;
+        }
+        let parent = self.stack.last().and_then(|info| info.base.as_ref());
+        match attrs
+            .iter()
+            .find(|&&(name, _)| name.expanded() == expanded_name!(xml "base"))
+        {
+            Some(&(_, value)) => Some(match parent {
+                Some(base) => uri::resolve(base, value),
+                None => value.to_owned(),
+            }),
+            None => parent.cloned(),
+        }
+    }
+
+    /// Returns how an attribute is written with `boolean_attributes`.
+    fn boolean_attribute_style(&self, name: &QualName) -> BooleanAttributeStyle {
+        if name.prefix.is_none() &&
+            name.ns.is_empty() &&
+            self.opts.boolean_attributes.contains(&name.local)
+        {
+            self.opts.boolean_attribute_style
+        } else {
+            BooleanAttributeStyle::Value
+        }
+    }
+
+    /// Returns the resolved value of an attribute listed in
+    /// `resolve_relative_uris_for`, if there is a base to resolve it against.
+    fn resolve_attr_value(&self, name: &QualName, value: &str) -> Option<String> {
+        let base = self.stack.last().and_then(|info| info.base.as_ref())?;
+        let listed = self
+            .opts
+            .resolve_relative_uris_for
+            .iter()
+            .any(|uri_attr| uri_attr.expanded() == name.expanded());
+        if listed && name.expanded() != expanded_name!(xml "base") {
+            Some(uri::resolve(base, value))
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    fn check_document(&self) -> bool {
+        self.opts.require_well_formed && self.opts.document_scope
+    }
+
+    /// With `c14n` at document scope, whether a node at the top level is
+    /// before (`Prolog`) or after (`Epilog`) the root element.
+    fn c14n_top_level(&self) -> Option<DocumentPhase> {
+        if self.opts.c14n && self.opts.document_scope && self.namespace_stack.depth() == 0 {
+            Some(self.phase)
+        } else {
+            None
+        }
+    }
+
+    /// Writes out any text held back by `coalesce_text`.
+    ///
+    /// This is called before every non-text node, and by `flush`, which
+    /// [`serialize`] calls once the whole node has been written. Callers
+    /// driving the serializer by hand need to call one of them after the
+    /// last node.
+    ///
+    /// [`serialize`]: fn.serialize.html
+    pub fn flush_text(&mut self) -> io::Result<()> {
+        if self.pending_text.is_empty() {
+            return Ok(());
+        }
+        self.check_text(&self.pending_text)?;
+        let fixed = fix_xml_chars(&self.pending_text, &self.opts);
+        let text = self.normalize_text(&fixed);
+        if self.in_raw_text() {
+            self.writer.write_all(text.as_bytes())?;
+        } else {
+            write_escaped(&mut self.writer, &text, EscapeContext::Text, &self.opts)?;
+        }
+        self.pending_text.clear();
+        Ok(())
+    }
+
+    /// Whether text goes unescaped in the current element.
+    fn in_raw_text(&self) -> bool {
+        self.stack.last().map_or(false, |info| info.raw_text)
+    }
+
+    /// The innermost open element, if any.
+    fn current_element(&self) -> Option<&QualName> {
+        self.stack.last().and_then(|info| info.name.as_ref())
+    }
+
+    /// With `require_well_formed` and `WellFormedPolicy::Error`, checks
+    /// that text to be written in the current element only has characters
+    /// allowed in XML. Restricted characters are fine, as they are written
+    /// as references.
+    fn check_text(&self, text: &str) -> io::Result<()> {
+        if self.opts.require_well_formed && self.opts.well_formed_policy == WellFormedPolicy::Error
+        {
+            check_xml_chars(
+                text,
+                self.current_element(),
+                None,
+                self.opts.xml_version,
+                false,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Whether `xml:space="preserve"` is in effect for the innermost open
+    /// element: the nearest `xml:space` attribute on it or its ancestors is
+    /// `preserve`. False outside any element.
+    pub fn preserve_space(&self) -> bool {
+        self.stack.last().map_or(false, |info| info.preserve_space)
+    }
+
+    /// Applies `trim_text` and `text_whitespace` to a run of text.
+    fn normalize_text<'t>(&self, text: &'t str) -> Cow<'t, str> {
+        if self.preserve_space() {
+            return Cow::Borrowed(text);
+        }
+        let is_space = |c| matches!(c, ' ' | '\t' | '\n' | '\r');
+        let text = if self.opts.trim_text || self.opts.text_whitespace == WhitespacePolicy::Trim {
+            text.trim_matches(is_space)
+        } else {
+            text
+        };
+        if self.opts.text_whitespace != WhitespacePolicy::Collapse ||
+            !text.contains(|c| matches!(c, '\t' | '\n' | '\r')) && !text.contains("  ")
+        {
+            return Cow::Borrowed(text);
+        }
+        let mut collapsed = String::with_capacity(text.len());
+        let mut in_space = false;
+        for c in text.chars() {
+            if is_space(c) {
+                if !in_space {
+                    collapsed.push(' ');
+                }
+                in_space = true;
+            } else {
+                collapsed.push(c);
+                in_space = false;
+            }
+        }
+        Cow::Owned(collapsed)
+    }
+
+    /// Checks whether the prefix of `name` is bound to its namespace in the
+    /// innermost scope that mentions the prefix at all.
+    ///
+    /// A default namespace that was never declared (or was undeclared with
+    /// `xmlns=""`) counts as being bound to the null namespace.
+    fn find_uri(&self, name: &QualName) -> bool {
+        for stack in self.namespace_stack.0.iter().rev() {
+            if let Some(uri) = stack.get(&name.prefix) {
+                return match *uri {
+                    Some(ref ns) => *ns == name.ns,
+                    None => name.ns.is_empty(),
+                };
+            }
+        }
+        name.prefix.is_none() && name.ns.is_empty()
+    }
+
+    /// Declares the namespace of `name` in the current scope, unless it is
+    /// already bound by an enclosing one.
+    ///
+    /// For an unprefixed element in the null namespace this emits `xmlns=""`
+    /// when some ancestor declared a default namespace.
+    fn find_or_insert_ns(&mut self, name: &QualName) {
+        if !self.find_uri(name) {
+            if let Some(last_ns) = self.namespace_stack.0.last_mut() {
+                last_ns.insert(name);
+            }
+        }
+    }
+
+    /// Makes sure an attribute's namespace is declared, returning the name it
+    /// has to be written under if that differs from `name`.
+    ///
+    /// Attributes never take the default namespace, so:
+    ///
+    /// * an unprefixed attribute in the null namespace is written as is;
+    /// * a prefixed attribute keeps its prefix, which is declared if it is not
+    ///   already bound to the attribute's namespace;
+    /// * an unprefixed attribute in a namespace is given a prefix: `xml` for
+    ///   the XML namespace, otherwise a new `nsN` prefix that is declared on
+    ///   the element.
+    ///
+    /// With `minimize_attr_prefixes`, a prefix already bound to the
+    /// attribute's namespace is used instead of declaring one in the
+    /// last two cases.
+    fn find_or_insert_attr_ns(&mut self, name: &QualName) -> io::Result<Option<QualName>> {
+        if name.ns.is_empty() && name.prefix.is_none() {
+            return Ok(None);
+        }
+        if name.prefix.is_some() && self.find_uri(name) {
+            return Ok(None);
+        }
+        let reused = if name.ns == ns!(xml) {
+            Some(namespace_prefix!("xml"))
+        } else if self.opts.minimize_attr_prefixes {
+            self.prefix_for_ns(&name.ns)
+        } else {
+            None
+        };
+        let prefix = match (reused, &name.prefix) {
+            (Some(prefix), _) => prefix,
+            (None, &Some(_)) => {
+                self.find_or_insert_ns(name);
+                return Ok(None);
+            },
+            (None, &None) => {
+                let prefix = self.unused_prefix()?;
+                if let Some(last_ns) = self.namespace_stack.0.last_mut() {
+                    last_ns.declare(Some(prefix.clone()), Some(name.ns.clone()));
+                }
+                prefix
+            },
+        };
+        Ok(Some(QualName::new(
+            Some(prefix),
+            name.ns.clone(),
+            name.local.clone(),
+        )))
+    }
+
+    /// Finds a prefix (never the default namespace) that is currently bound
+    /// to `ns`.
+    fn prefix_for_ns(&self, ns: &Namespace) -> Option<Prefix> {
+        for scope in self.namespace_stack.0.iter().rev() {
+            for (prefix, uri) in scope.get_scope_iter() {
+                match (prefix, uri) {
+                    (&Some(ref prefix), &Some(ref uri)) if uri == ns => {
+                        let name = QualName::new(Some(prefix.clone()), ns.clone(), local_name!(""));
+                        if self.find_uri(&name) {
+                            return Some(prefix.clone());
+                        }
+                    },
+                    _ => {},
+                }
+            }
+        }
+        None
+    }
+
+    /// Picks an `nsN` prefix, with `generated_prefix_prefix` in place of
+    /// `ns`, that is not bound in any scope.
+    fn unused_prefix(&self) -> io::Result<Prefix> {
+        let start = &self.opts.generated_prefix_prefix;
+        if !is_valid_ncname(start) ||
+            start.ends_with(|c: char| c.is_ascii_digit()) ||
+            start
+                .get(..3)
+                .map_or(false, |s| s.eq_ignore_ascii_case("xml"))
+        {
+            return Err(SerializeError::InvalidGeneratedPrefix(start.clone()).into());
+        }
+        Ok((1..)
+            .map(|n| Prefix::from(format!("{}{}", self.opts.generated_prefix_prefix, n)))
+            .find(|prefix| {
+                let prefix = Some(prefix.clone());
+                self.namespace_stack
+                    .0
+                    .iter()
+                    .all(|scope| scope.get(&prefix).is_none())
+            })
+            .unwrap())
+    }
+
+    /// Records an explicit `xmlns` or `xmlns:prefix` attribute in the current
+    /// scope, so that it is written once alongside the declarations the
+    /// serializer adds itself.
+    fn declare_ns(&mut self, name: &QualName, value: &str) {
+        let prefix = match name.prefix {
+            Some(_) => Some(Prefix::from(&*name.local)),
+            None => None,
+        };
+        let uri = if value.is_empty() {
+            None
+        } else {
+            Some(Namespace::from(value))
+        };
+        if self.opts.c14n {
+            let scopes = &self.namespace_stack.0;
+            let outer = scopes[..scopes.len() - 1]
+                .iter()
+                .rev()
+                .filter_map(|scope| scope.get(&prefix))
+                .next();
+            if outer == Some(&uri) {
+                return;
+            }
+        }
+        if let Some(last_ns) = self.namespace_stack.0.last_mut() {
+            last_ns.declare(prefix, uri);
+        }
+    }
+}
+
+impl<Wr: Write> Serializer for XmlSerializer<Wr> {
+    /// Serializes given start element into text. Start element contains
+    /// qualified name and an attributes iterator.
+    fn start_elem<'a, AttrIter>(&mut self, name: QualName, attrs: AttrIter) -> io::Result<()>
+    where
+        AttrIter: Iterator<Item = AttrRef<'a>>,
+    {
+        self.flush_text()?;
+        if self.namespace_stack.depth() == 0 {
+            if self.phase == DocumentPhase::Epilog && self.check_document() {
+                return Err(SerializeError::MultipleRoots(name).into());
+            }
+            self.phase = DocumentPhase::Element;
+        }
+        // Every namespace used by the element or its attributes has to be
+        // known before the declarations are written, so this takes two passes.
+        let mut attrs: Vec<AttrRef> = attrs.collect();
+        if self.opts.canonicalize {
+            attrs.sort_by(|a, b| canonical_attribute_order(a.0, b.0));
+        }
+        let name = if self.opts.require_well_formed {
+            name
+        } else {
+            without_xmlns_prefix(name)
+        };
+        if self.opts.require_well_formed {
+            let prefix_ok = name.prefix.as_ref().map_or(true, |p| is_valid_ncname(p));
+            if !prefix_ok || !is_valid_ncname(&name.local) {
+                return Err(SerializeError::InvalidName(name).into());
+            }
+            if name.prefix == Some(namespace_prefix!("xmlns")) {
+                return Err(SerializeError::ReservedPrefix(name).into());
+            }
+            let mut seen = HashSet::new();
+            for &(attr_name, value) in attrs.iter() {
+                if !seen.insert(attr_name.expanded()) {
+                    return Err(SerializeError::DuplicateAttribute(attr_name.clone()).into());
+                }
+                if self.opts.well_formed_policy == WellFormedPolicy::Error {
+                    check_xml_chars(
+                        value,
+                        Some(&name),
+                        Some(attr_name),
+                        self.opts.xml_version,
+                        false,
+                    )?;
+                }
+            }
+        }
+        if !is_allowed(&self.opts.allowed_elements, &name) {
+            return Err(SerializeError::DisallowedName(name).into());
+        }
+        for &(attr_name, _) in attrs.iter() {
+            if attr_name.ns != ns!(xmlns) && !is_allowed(&self.opts.allowed_attributes, attr_name) {
+                return Err(SerializeError::DisallowedName(attr_name.clone()).into());
+            }
+        }
+        if !self.opts.allow_default_ns_undeclaration &&
+            name.prefix.is_none() &&
+            name.ns.is_empty() &&
+            !self.find_uri(&name)
+        {
+            return Err(SerializeError::CannotUndeclareDefaultNamespace(name).into());
+        }
+        let base = self.element_base(&attrs);
+        let preserve_space = match attrs
+            .iter()
+            .find(|&&(name, _)| name.expanded() == expanded_name!(xml "space"))
+        {
+            Some(&(_, "preserve")) => true,
+            Some(&(_, "default")) => false,
+            _ => self.preserve_space(),
+        };
+        self.indent_child()?;
+        let mixed = preserve_space || self.stack.last().map_or(false, |parent| parent.mixed);
+        self.stack.push(ElemInfo {
+            name: Some(name.clone()),
+            base: base,
+            preserve_space: preserve_space,
+            mixed: mixed,
+            indented: false,
+            raw_text: self.opts.raw_text_elements &&
+                name.ns == ns!(html) &&
+                is_raw_text_element(&name.local),
+            namespace_scope: false,
+        });
+        self.namespace_stack.push(NamespaceMap::empty());
+        for &(attr_name, value) in attrs.iter() {
+            if attr_name.ns == ns!(xmlns) {
+                self.declare_ns(attr_name, value);
+            }
+        }
+        self.find_or_insert_ns(&name);
+        // Renaming is rare, so only the renamed attributes are kept, by index.
+        // Prefixed attributes go first, so that a generated prefix never
+        // takes one that a later attribute asks for.
+        let mut renamed: Vec<(usize, QualName)> = vec![];
+        for &prefixed in &[true, false] {
+            for (i, &(attr_name, _)) in attrs.iter().enumerate() {
+                if attr_name.ns != ns!(xmlns) && attr_name.prefix.is_some() == prefixed {
+                    if let Some(new_name) = self.find_or_insert_attr_ns(attr_name)? {
+                        renamed.push((i, new_name));
+                    }
+                }
+            }
+        }
+        renamed.sort_by_key(|&(i, _)| i);
+
+        self.writer.write_all(b"<")?;
+        write_qual_name(&mut self.writer, &name)?;
+        if let Some(current_namespace) = self.namespace_stack.0.last() {
+            for (prefix, url_opt) in current_namespace.get_scope_iter() {
+                self.writer.write_all(b" xmlns")?;
+                if let &Some(ref p) = prefix {
+                    self.writer.write_all(b":")?;
+                    self.writer.write_all(&*p.as_bytes())?;
+                }
+
+                let url = url_opt.as_ref().map_or("", |url| &**url);
+                write_attr_value(&mut self.writer, url, &self.opts)?;
+            }
+        }
+        // Unprefixed attributes in the null namespace are never renamed, and
+        // unless they are listed in an option, written as they are.
+        let plain_fast_path = self.opts.boolean_attributes.is_empty() &&
+            self.opts.resolve_relative_uris_for.is_empty();
+        let mut renamed = renamed.iter().peekable();
+        for (i, (name, value)) in attrs.into_iter().enumerate() {
+            let fixed = fix_xml_chars(value, &self.opts);
+            let value = &*fixed;
+            if plain_fast_path && name.prefix.is_none() && name.ns == ns!() {
+                self.writer.write_all(b" ")?;
+                self.writer.write_all(name.local.as_bytes())?;
+                write_attr_value(&mut self.writer, value, &self.opts)?;
+                continue;
+            }
+            if name.ns == ns!(xmlns) {
+                continue;
+            }
+            let name = match renamed.peek() {
+                Some(&&(j, ref new_name)) if i == j => {
+                    renamed.next();
+                    new_name
+                },
+                _ => name,
+            };
+            self.writer.write_all(b" ")?;
+            write_qual_name(&mut self.writer, name)?;
+            let value = match self.boolean_attribute_style(name) {
+                BooleanAttributeStyle::Value => value,
+                BooleanAttributeStyle::Empty => "",
+                BooleanAttributeStyle::Name => &*name.local,
+                BooleanAttributeStyle::Minimized => continue,
+            };
+            let resolved = self.resolve_attr_value(name, value);
+            let value = resolved.as_ref().map_or(value, |v| &**v);
+            write_attr_value(&mut self.writer, value, &self.opts)?;
+        }
+        self.writer.write_all(b">")?;
+        if let Some(ref hook) = self.opts.on_element {
+            hook(&name, self.namespace_stack.depth() - 1);
+        }
+        Ok(())
+    }
+
+    /// Serializes given end element into text.
+    fn end_elem(&mut self, name: QualName) -> io::Result<()> {
+        self.flush_text()?;
+        let name = without_xmlns_prefix(name);
+        if self.opts.require_well_formed {
+            let open = self
+                .stack
+                .last()
+                .filter(|info| !info.namespace_scope)
+                .and_then(|info| info.name.as_ref());
+            if open != Some(&name) {
+                return Err(SerializeError::MismatchedEndTag {
+                    expected: open.cloned(),
+                    found: name,
+                }
+                .into());
+            }
+        }
+        match self.stack.pop() {
+            Some(info) => {
+                if info.indented && !info.mixed {
+                    self.write_indent(self.namespace_stack.depth() - 1)?;
+                }
+                self.namespace_stack.pop();
+                if self.namespace_stack.depth() == 0 {
+                    self.phase = DocumentPhase::Epilog;
+                }
+            },
+            None => warn!("end tag {:?} with no open element", name.local),
+        }
+        self.writer.write_all(b"</")?;
+        write_qual_name(&mut self.writer, &name)?;
+        self.writer.write_all(b">")
+    }
+
+    /// Serializes comment into text.
+    fn write_comment(&mut self, text: &str) -> io::Result<()> {
+        if self.opts.strip_comments {
+            return Ok(());
+        }
+        self.flush_text()?;
+        #[allow(deprecated)]
+        let fallback = match self.opts.comment_fallback {
+            CommentFallback::Error if self.opts.auto_fix_comments => CommentFallback::Split,
+            fallback => fallback,
+        };
+        let text = fix_comment(text, fallback);
+        if self.opts.require_well_formed {
+            check_xml_chars(
+                &text,
+                self.current_element(),
+                None,
+                self.opts.xml_version,
+                true,
+            )?;
+            if text.contains("--") || text.ends_with('-') {
+                return Err(SerializeError::InvalidComment.into());
+            }
+        }
+        let top_level = self.c14n_top_level();
+        if top_level == Some(DocumentPhase::Epilog) {
+            self.writer.write_all(b"\n")?;
+        }
+        self.indent_child()?;
+        self.writer.write_all(b"<!--")?;
+        self.escape(&text, EscapeContext::Comment)?;
+        self.writer.write_all(b"-->")?;
+        if top_level == Some(DocumentPhase::Prolog) {
+            self.writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Serializes given doctype
+    fn write_doctype(&mut self, name: &str) -> io::Result<()> {
+        self.write_doctype_full(name, None, None)
+    }
+
+    /// Serializes a doctype with `PUBLIC` or `SYSTEM` identifiers.
+    fn write_doctype_full(
+        &mut self,
+        name: &str,
+        public_id: Option<&str>,
+        system_id: Option<&str>,
+    ) -> io::Result<()> {
+        if self.opts.c14n {
+            return Ok(());
+        }
+        self.flush_text()?;
+        if self.phase != DocumentPhase::Prolog && self.check_document() {
+            return Err(SerializeError::MisplacedDoctype.into());
+        }
+        if self.seen_doctype && self.check_document() {
+            return Err(SerializeError::MultipleDoctypes.into());
+        }
+        if self.opts.require_well_formed {
+            if let Some(public_id) = public_id {
+                if system_id.is_none() || !public_id.chars().all(is_pubid_char) {
+                    return Err(SerializeError::InvalidPublicId.into());
+                }
+            }
+            if let Some(system_id) = system_id {
+                if system_id.contains('"') && system_id.contains('\'') {
+                    return Err(SerializeError::InvalidSystemId.into());
+                }
+            }
+        }
+        self.seen_doctype = true;
+        self.writer.write_all(b"<!DOCTYPE ")?;
+        self.writer.write_all(name.as_bytes())?;
+        match public_id {
+            Some(public_id) => {
+                self.writer.write_all(b" PUBLIC ")?;
+                write_doctype_literal(&mut self.writer, public_id)?;
+            },
+            None if system_id.is_some() => self.writer.write_all(b" SYSTEM")?,
+            None => {},
+        }
+        if let Some(system_id) = system_id {
+            self.writer.write_all(b" ")?;
+            write_doctype_literal(&mut self.writer, system_id)?;
+        }
+        self.writer.write_all(b">")
+    }
+
+    /// Serializes text for a node or an attributes.
+    fn write_text(&mut self, text: &str) -> io::Result<()> {
+        if self.namespace_stack.depth() == 0 &&
+            self.check_document() &&
+            !text.chars().all(|c| matches!(c, ' ' | '\t' | '\n' | '\r'))
+        {
+            return Err(SerializeError::TextOutsideRoot.into());
+        }
+        if self.c14n_top_level().is_some() {
+            return Ok(());
+        }
+        if !self.normalize_text(text).is_empty() {
+            self.mark_mixed();
+        }
+        if self.opts.coalesce_text {
+            self.pending_text.push_str(text);
+            return Ok(());
+        }
+        self.check_text(text)?;
+        let fixed = fix_xml_chars(text, &self.opts);
+        let text = self.normalize_text(&fixed);
+        if self.in_raw_text() {
+            return self.writer.write_all(text.as_bytes());
+        }
+        self.escape(&text, EscapeContext::Text)
     }
 
     /// Serializes given processing instruction.
     fn write_processing_instruction(&mut self, target: &str, data: &str) -> io::Result<()> {
+        if self.opts.strip_processing_instructions {
+            return Ok(());
+        }
+        self.flush_text()?;
+        if self.opts.require_well_formed {
+            if !is_valid_ncname(target) || target.eq_ignore_ascii_case("xml") {
+                return Err(SerializeError::InvalidPiTarget(target.to_owned()).into());
+            }
+            check_xml_chars(
+                data,
+                self.current_element(),
+                None,
+                self.opts.xml_version,
+                true,
+            )?;
+            if data.contains("?>") {
+                return Err(SerializeError::InvalidPiData.into());
+            }
+        }
+        let top_level = self.c14n_top_level();
+        if top_level == Some(DocumentPhase::Epilog) {
+            self.writer.write_all(b"\n")?;
+        }
+        self.indent_child()?;
         self.writer.write_all(b"<?")?;
         self.writer.write_all(target.as_bytes())?;
-        self.writer.write_all(b" ")?;
-        self.writer.write_all(data.as_bytes())?;
-        self.writer.write_all(b"?>")
+        if !(self.opts.c14n && data.is_empty()) {
+            self.writer.write_all(b" ")?;
+        }
+        self.escape(data, EscapeContext::ProcessingInstruction)?;
+        self.writer.write_all(b"?>")?;
+        if top_level == Some(DocumentPhase::Prolog) {
+            self.writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Writes `raw` without escaping. With `require_well_formed`, it may
+    /// only contain characters allowed in XML, but is not checked further.
+    fn write_raw(&mut self, raw: &str) -> io::Result<()> {
+        self.flush_text()?;
+        if self.opts.require_well_formed {
+            check_xml_chars(
+                raw,
+                self.current_element(),
+                None,
+                self.opts.xml_version,
+                true,
+            )?;
+        }
+        self.writer.write_all(raw.as_bytes())
+    }
+
+    /// Writes out pending text and flushes the writer.
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_text()?;
+        self.writer.flush()
+    }
+
+    /// Serializes a CDATA section.
+    fn write_cdata(&mut self, text: &str) -> io::Result<()> {
+        if self.opts.c14n {
+            return self.write_text(text);
+        }
+        if self.namespace_stack.depth() == 0 && self.check_document() {
+            return Err(SerializeError::TextOutsideRoot.into());
+        }
+        self.flush_text()?;
+        self.mark_mixed();
+        if self.opts.require_well_formed {
+            check_xml_chars(
+                text,
+                self.current_element(),
+                None,
+                self.opts.xml_version,
+                true,
+            )?;
+            if text.contains("]]>") {
+                return Err(SerializeError::InvalidCdata.into());
+            }
+        }
+        self.writer.write_all(b"<![CDATA[")?;
+        self.escape(text, EscapeContext::Cdata)?;
+        self.writer.write_all(b"]]>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::iter;
+
+    fn qual(local: &str) -> QualName {
+        QualName::new(None, ns!(), local.into())
+    }
+
+    fn prefixed(prefix: &str, ns: &str, local: &str) -> QualName {
+        QualName::new(Some(prefix.into()), ns.into(), local.into())
+    }
+
+    fn start<Wr: Write>(ser: &mut XmlSerializer<Wr>, name: QualName, attrs: &[(QualName, &str)]) {
+        ser.start_elem(name, attrs.iter().map(|&(ref n, v)| (n, v)))
+            .unwrap();
+    }
+
+    fn error(err: io::Error) -> SerializeError {
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        err.get_ref()
+            .and_then(|e| e.downcast_ref::<SerializeError>())
+            .cloned()
+            .unwrap()
+    }
+
+    fn document_opts() -> SerializeOpts {
+        SerializeOpts {
+            require_well_formed: true,
+            document_scope: true,
+            ..opts()
+        }
+    }
+
+    fn serialize_nested(outer: QualName, inner: QualName) -> String {
+        let mut ser = XmlSerializer::new(Vec::new(), opts());
+        start(&mut ser, outer.clone(), &[]);
+        start(&mut ser, inner.clone(), &[]);
+        ser.end_elem(inner).unwrap();
+        ser.end_elem(outer).unwrap();
+        output(ser)
+    }
+
+    /// A minimal tree, for exercising `serialize` itself.
+    enum Node {
+        Element(QualName, Vec<(QualName, &'static str)>, Vec<Node>),
+        Text(&'static str),
+        Comment(&'static str),
+        Pi(&'static str, &'static str),
+        Doctype(&'static str),
+    }
+
+    impl Node {
+        fn write<S: Serializer>(&self, serializer: &mut S) -> io::Result<()> {
+            match *self {
+                Node::Element(ref name, ref attrs, ref children) => {
+                    serializer.start_elem(name.clone(), attrs.iter().map(|&(ref n, v)| (n, v)))?;
+                    for child in children {
+                        child.write(serializer)?;
+                    }
+                    serializer.end_elem(name.clone())
+                },
+                Node::Text(text) => serializer.write_text(text),
+                Node::Comment(text) => serializer.write_comment(text),
+                Node::Pi(target, data) => serializer.write_processing_instruction(target, data),
+                Node::Doctype(name) => serializer.write_doctype(name),
+            }
+        }
+    }
+
+    impl Serialize for Node {
+        fn serialize<S>(
+            &self,
+            serializer: &mut S,
+            traversal_scope: TraversalScope,
+        ) -> io::Result<()>
+        where
+            S: Serializer,
+        {
+            match (traversal_scope, self) {
+                (TraversalScope::ChildrenOnly(_), &Node::Element(_, _, ref children)) => {
+                    for child in children {
+                        child.write(serializer)?;
+                    }
+                    Ok(())
+                },
+                _ => self.write(serializer),
+            }
+        }
+    }
+
+    fn opts() -> SerializeOpts {
+        SerializeOpts {
+            traversal_scope: TraversalScope::IncludeNode,
+            ..Default::default()
+        }
+    }
+
+    fn output(ser: XmlSerializer<Vec<u8>>) -> String {
+        String::from_utf8(ser.writer.inner).unwrap()
+    }
+
+    #[test]
+    fn coalesced_text_spanning_cdata_end() {
+        for &coalesce in [false, true].iter() {
+            let mut ser = XmlSerializer::new(
+                Vec::new(),
+                SerializeOpts {
+                    coalesce_text: coalesce,
+                    ..opts()
+                },
+            );
+            ser.start_elem(qual("a"), vec![].into_iter()).unwrap();
+            ser.write_text("x]").unwrap();
+            ser.write_text("]").unwrap();
+            ser.write_text(">y").unwrap();
+            if coalesce {
+                assert_eq!(ser.writer.inner, b"<a>");
+            }
+            ser.end_elem(qual("a")).unwrap();
+            assert_eq!(output(ser), "<a>x]]&gt;y</a>");
+        }
+    }
+
+    #[test]
+    fn coalesced_text_flushed_before_comment() {
+        let mut ser = XmlSerializer::new(
+            Vec::new(),
+            SerializeOpts {
+                coalesce_text: true,
+                ..opts()
+            },
+        );
+        ser.write_text("a&").unwrap();
+        ser.write_text("b").unwrap();
+        ser.write_comment("c").unwrap();
+        ser.write_text("<d").unwrap();
+        ser.flush_text().unwrap();
+        assert_eq!(output(ser), "a&amp;b<!--c-->&lt;d");
+    }
+
+    #[test]
+    fn prefix_bound_by_ancestor_is_not_redeclared() {
+        assert_eq!(
+            serialize_nested(prefixed("a", "foo", "x"), prefixed("a", "foo", "y")),
+            "<a:x xmlns:a=\"foo\"><a:y></a:y></a:x>"
+        );
+    }
+
+    #[test]
+    fn prefix_bound_to_other_namespace_is_redeclared() {
+        assert_eq!(
+            serialize_nested(prefixed("a", "foo", "x"), prefixed("a", "bar", "y")),
+            "<a:x xmlns:a=\"foo\"><a:y xmlns:a=\"bar\"></a:y></a:x>"
+        );
+    }
+
+    #[test]
+    fn unseen_prefix_is_declared() {
+        assert_eq!(
+            serialize_nested(prefixed("a", "foo", "x"), prefixed("b", "bar", "y")),
+            "<a:x xmlns:a=\"foo\"><b:y xmlns:b=\"bar\"></b:y></a:x>"
+        );
+    }
+
+    #[test]
+    fn null_namespace_undeclares_default() {
+        let outer = QualName::new(None, "foo".into(), "x".into());
+        assert_eq!(
+            serialize_nested(outer, qual("y")),
+            "<x xmlns=\"foo\"><y xmlns=\"\"></y></x>"
+        );
+        assert_eq!(serialize_nested(qual("x"), qual("y")), "<x><y></y></x>");
+    }
+
+    #[test]
+    fn attribute_prefix_is_declared() {
+        let mut ser = XmlSerializer::new(Vec::new(), opts());
+        start(
+            &mut ser,
+            qual("x"),
+            &[
+                (prefixed("b", "bar", "q"), "1"),
+                (prefixed("xml", &ns!(xml), "lang"), "en"),
+            ],
+        );
+        ser.end_elem(qual("x")).unwrap();
+        assert_eq!(
+            output(ser),
+            "<x xmlns:b=\"bar\" b:q=\"1\" xml:lang=\"en\"></x>"
+        );
+    }
+
+    #[test]
+    fn explicit_declaration_is_written_once() {
+        let mut ser = XmlSerializer::new(Vec::new(), opts());
+        let name = prefixed("a", "foo", "x");
+        start(
+            &mut ser,
+            name.clone(),
+            &[(prefixed("xmlns", &ns!(xmlns), "a"), "foo")],
+        );
+        ser.end_elem(name).unwrap();
+        assert_eq!(output(ser), "<a:x xmlns:a=\"foo\"></a:x>");
+    }
+
+    #[test]
+    fn processing_instruction_checks() {
+        let strict = SerializeOpts {
+            require_well_formed: true,
+            ..opts()
+        };
+        let mut ser = XmlSerializer::new(vec![], strict.clone());
+        ser.write_processing_instruction("xml-stylesheet", "href=\"a.xsl\"")
+            .unwrap();
+        ser.write_processing_instruction("xmlfoo", "").unwrap();
+        assert_eq!(output(ser), "<?xml-stylesheet href=\"a.xsl\"?><?xmlfoo ?>");
+
+        for target in &["xml", "XmL", "a:b", "", "1pi"] {
+            let mut ser = XmlSerializer::new(vec![], strict.clone());
+            let err = ser.write_processing_instruction(target, "").unwrap_err();
+            assert_eq!(
+                error(err),
+                SerializeError::InvalidPiTarget(target.to_string())
+            );
+        }
+        let mut ser = XmlSerializer::new(vec![], strict);
+        let err = ser
+            .write_processing_instruction("pi", "a ?> b")
+            .unwrap_err();
+        assert_eq!(error(err), SerializeError::InvalidPiData);
+
+        let mut ser = XmlSerializer::new(vec![], opts());
+        ser.write_processing_instruction("a:xml", "?>").unwrap();
+        assert_eq!(output(ser), "<?a:xml ?>?>");
+    }
+
+    #[test]
+    fn raw_text_elements() {
+        let html = |local| QualName::new(None, ns!(html), LocalName::from(local));
+        let tree = Node::Element(
+            html("div"),
+            vec![],
+            vec![
+                Node::Element(
+                    html("script"),
+                    vec![],
+                    vec![Node::Text("if (a < b && c) {}")],
+                ),
+                Node::Element(qual("style"), vec![], vec![Node::Text("a > b")]),
+                Node::Text("<&>"),
+            ],
+        );
+        let raw = |coalesce_text| SerializeOpts {
+            raw_text_elements: true,
+            coalesce_text: coalesce_text,
+            ..opts()
+        };
+        let expected = concat!(
+            r#"<div xmlns="http://www.w3.org/1999/xhtml"><script>if (a < b && c) {}</script>"#,
+            r#"<style xmlns="">a &gt; b</style>&lt;&amp;&gt;</div>"#
+        );
+        assert_eq!(serialize_to_string(&tree, raw(false)).unwrap(), expected);
+        assert_eq!(serialize_to_string(&tree, raw(true)).unwrap(), expected);
+        assert_eq!(
+            serialize_to_string(&tree, opts()).unwrap(),
+            expected.replace("a < b && c", "a &lt; b &amp;&amp; c")
+        );
+
+        let strict = SerializeOpts {
+            require_well_formed: true,
+            ..raw(false)
+        };
+        let tree = Node::Element(html("script"), vec![], vec![Node::Text("<\0")]);
+        let err = serialize_to_string(&tree, strict).unwrap_err();
+        assert_eq!(
+            error(err),
+            SerializeError::NotXmlChar {
+                character: '\0',
+                index: 1,
+                attribute: None,
+                element: Some(html("script")),
+            }
+        );
+    }
+
+    #[test]
+    fn prolog_and_epilog_nodes() {
+        let mut ser = XmlSerializer::new(Vec::new(), document_opts());
+        ser.write_processing_instruction("xml-stylesheet", "href=\"a.xsl\"")
+            .unwrap();
+        ser.write_text("\n").unwrap();
+        ser.write_doctype("root").unwrap();
+        start(&mut ser, qual("root"), &[]);
+        ser.write_comment("inside").unwrap();
+        ser.end_elem(qual("root")).unwrap();
+        ser.write_comment("epilog").unwrap();
+        assert_eq!(
+            output(ser),
+            "<?xml-stylesheet href=\"a.xsl\"?>\n<!DOCTYPE root><root><!--inside--></root><!--epilog-->"
+        );
+    }
+
+    #[test]
+    fn second_root_is_rejected() {
+        let mut ser = XmlSerializer::new(Vec::new(), document_opts());
+        start(&mut ser, qual("a"), &[]);
+        ser.end_elem(qual("a")).unwrap();
+        let err = ser.start_elem(qual("b"), vec![].into_iter()).unwrap_err();
+        assert_eq!(error(err), SerializeError::MultipleRoots(qual("b")));
+        assert_eq!(
+            error(ser.write_text("text").unwrap_err()),
+            SerializeError::TextOutsideRoot
+        );
+        assert_eq!(
+            error(ser.write_doctype("a").unwrap_err()),
+            SerializeError::MisplacedDoctype
+        );
+
+        let mut ser = XmlSerializer::new(Vec::new(), opts());
+        start(&mut ser, qual("a"), &[]);
+        ser.end_elem(qual("a")).unwrap();
+        start(&mut ser, qual("b"), &[]);
+        ser.end_elem(qual("b")).unwrap();
+        assert_eq!(output(ser), "<a></a><b></b>");
+    }
+
+    #[test]
+    fn escape_per_context() {
+        let text = "<a href='x'>\"&\"</a>";
+        let cases = [
+            (EscapeContext::Text, "&lt;a href='x'&gt;\"&amp;\"&lt;/a&gt;"),
+            (
+                EscapeContext::Attribute(QuoteStyle::Double),
+                "&lt;a href='x'&gt;&quot;&amp;&quot;&lt;/a&gt;",
+            ),
+            (
+                EscapeContext::Attribute(QuoteStyle::Single),
+                "&lt;a href=&apos;x&apos;&gt;\"&amp;\"&lt;/a&gt;",
+            ),
+            (EscapeContext::Comment, text),
+            (EscapeContext::ProcessingInstruction, text),
+            (EscapeContext::Cdata, text),
+        ];
+        for &(context, expected) in cases.iter() {
+            let mut ser = XmlSerializer::new(Vec::new(), opts());
+            ser.escape(text, context).unwrap();
+            assert_eq!(output(ser), expected, "{:?}", context);
+        }
+    }
+
+    #[test]
+    fn fragment_with_several_roots() {
+        let foo = |local: &str| QualName::new(Some("f".into()), "foo".into(), local.into());
+        let fragment = Node::Element(
+            qual("fragment"),
+            vec![],
+            vec![
+                Node::Element(
+                    foo("a"),
+                    vec![],
+                    vec![Node::Element(foo("b"), vec![], vec![])],
+                ),
+                Node::Text(" & "),
+                Node::Comment("between"),
+                Node::Element(foo("c"), vec![], vec![]),
+            ],
+        );
+        let opts = SerializeOpts {
+            traversal_scope: TraversalScope::ChildrenOnly(None),
+            require_well_formed: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            serialize_to_string(&fragment, opts).unwrap(),
+            "<f:a xmlns:f=\"foo\"><f:b></f:b></f:a> &amp; <!--between--><f:c xmlns:f=\"foo\"></f:c>"
+        );
+    }
+
+    #[test]
+    fn text_bytes_are_validated() {
+        let mut ser = XmlSerializer::new(Vec::new(), opts());
+        ser.write_text_bytes("caf\u{e9} & co".as_bytes()).unwrap();
+        assert_eq!(
+            error(ser.write_text_bytes(b"ok\xff\xfe").unwrap_err()),
+            SerializeError::InvalidUtf8(2)
+        );
+        assert_eq!(output(ser), "caf\u{e9} &amp; co");
+    }
+
+    #[test]
+    fn minimal_escaping_keeps_gt_in_attributes() {
+        let attr = [(qual("cond"), "a > b && c < d")];
+        let mut results = vec![];
+        for &level in [EscapeLevel::Strict, EscapeLevel::Minimal].iter() {
+            let mut ser = XmlSerializer::new(
+                Vec::new(),
+                SerializeOpts {
+                    escape_level: level,
+                    ..opts()
+                },
+            );
+            start(&mut ser, qual("x"), &attr);
+            ser.write_text("1 > 0").unwrap();
+            ser.end_elem(qual("x")).unwrap();
+            results.push(output(ser));
+        }
+        assert_eq!(
+            results,
+            vec![
+                "<x cond=\"a &gt; b &amp;&amp; c &lt; d\">1 &gt; 0</x>",
+                "<x cond=\"a > b &amp;&amp; c &lt; d\">1 &gt; 0</x>",
+            ]
+        );
+    }
+
+    #[test]
+    fn element_signature_ignores_attribute_order() {
+        let attr = |name: QualName, value: &str| Attribute {
+            name: name,
+            value: value.into(),
+        };
+        let a = vec![
+            attr(qual("id"), "x"),
+            attr(prefixed("b", "bar", "q"), "1"),
+            attr(qual("class"), "c"),
+        ];
+        let b = vec![a[2].clone(), a[0].clone(), a[1].clone()];
+
+        let mut ser = XmlSerializer::new(Vec::new(), opts());
+        start(&mut ser, prefixed("b", "bar", "root"), &[]);
+        let signature = ser.element_signature(&qual("e"), &a).unwrap();
+        assert_eq!(signature, ser.element_signature(&qual("e"), &b).unwrap());
+        assert_eq!(signature, "<e class=\"c\" id=\"x\" b:q=\"1\">");
+        assert_eq!(output(ser), "<b:root xmlns:b=\"bar\">");
+
+        let indented = SerializeOpts {
+            indent: Some(IndentStyle::Tab),
+            ..opts()
+        };
+        let mut ser = XmlSerializer::new(Vec::new(), indented);
+        start(&mut ser, prefixed("b", "bar", "root"), &[]);
+        assert_eq!(ser.element_signature(&qual("e"), &a).unwrap(), signature);
+    }
+
+    #[test]
+    fn element_signature_skips_element_hook() {
+        use std::sync::Mutex;
+
+        let calls = Arc::new(Mutex::new(0));
+        let hook_calls = calls.clone();
+        let opts = SerializeOpts {
+            on_element: Some(Arc::new(move |_: &QualName, _| {
+                *hook_calls.lock().unwrap() += 1
+            })),
+            ..opts()
+        };
+        let mut ser = XmlSerializer::new(Vec::new(), opts);
+        start(&mut ser, qual("root"), &[]);
+        assert_eq!(*calls.lock().unwrap(), 1);
+        ser.element_signature(&qual("e"), &[]).unwrap();
+        assert_eq!(*calls.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn relative_uris_resolved_against_xml_base() {
+        let xml_base = || prefixed("xml", &ns!(xml), "base");
+        let tree = Node::Element(
+            qual("doc"),
+            vec![(xml_base(), "http://example.org/a/b/")],
+            vec![Node::Element(
+                qual("section"),
+                vec![(xml_base(), "../c/")],
+                vec![Node::Element(
+                    qual("link"),
+                    vec![(qual("href"), "d.xml#frag"), (qual("title"), "e.xml")],
+                    vec![],
+                )],
+            )],
+        );
+        let mut opts = opts();
+        assert_eq!(
+            serialize_to_string(&tree, opts.clone()).unwrap(),
+            "<doc xml:base=\"http://example.org/a/b/\"><section xml:base=\"../c/\">\
+             <link href=\"d.xml#frag\" title=\"e.xml\"></link></section></doc>"
+        );
+        opts.resolve_relative_uris_for
+            .insert(QualName::new(None, ns!(), local_name!("href")));
+        assert_eq!(
+            serialize_to_string(&tree, opts).unwrap(),
+            "<doc xml:base=\"http://example.org/a/b/\"><section xml:base=\"../c/\">\
+             <link href=\"http://example.org/a/c/d.xml#frag\" title=\"e.xml\"></link></section></doc>"
+        );
+    }
+
+    #[test]
+    fn attribute_whitespace_escaped_in_canonical_mode() {
+        let serialize_with = |opts: SerializeOpts| {
+            let mut ser = XmlSerializer::new(
+                Vec::new(),
+                SerializeOpts {
+                    traversal_scope: TraversalScope::IncludeNode,
+                    ..opts
+                },
+            );
+            start(&mut ser, qual("x"), &[(qual("a"), "1\t2\n3\r\n4 5")]);
+            ser.write_text("\t\n").unwrap();
+            ser.end_elem(qual("x")).unwrap();
+            output(ser)
+        };
+        assert_eq!(
+            serialize_with(SerializeOpts::default()),
+            "<x a=\"1\t2\n3\r\n4 5\">\t\n</x>"
+        );
+        assert_eq!(
+            serialize_with(SerializeOpts::canonical()),
+            "<x a=\"1&#x9;2&#xA;3&#xD;&#xA;4 5\">\t\n</x>"
+        );
+    }
+
+    #[test]
+    fn attributes_in_canonical_order() {
+        let attrs = || {
+            vec![
+                (prefixed("z", "urn:b", "attr"), "1"),
+                (qual("b"), "2"),
+                (prefixed("xmlns", &ns!(xmlns), "q"), "urn:q"),
+                (prefixed("y", "urn:a", "attr"), "3"),
+                (qual("a"), "4"),
+            ]
+        };
+        let serialize_with = |canonicalize| {
+            let opts = SerializeOpts {
+                canonicalize: canonicalize,
+                ..opts()
+            };
+            let mut ser = XmlSerializer::new(vec![], opts);
+            start(&mut ser, prefixed("q", "urn:q", "e"), &attrs());
+            output(ser)
+        };
+        assert_eq!(
+            serialize_with(false),
+            concat!(
+                r#"<q:e xmlns:q="urn:q" xmlns:y="urn:a" xmlns:z="urn:b" "#,
+                r#"z:attr="1" b="2" y:attr="3" a="4">"#
+            )
+        );
+        assert_eq!(
+            serialize_with(true),
+            concat!(
+                r#"<q:e xmlns:q="urn:q" xmlns:y="urn:a" xmlns:z="urn:b" "#,
+                r#"a="4" b="2" y:attr="3" z:attr="1">"#
+            )
+        );
+    }
+
+    // The examples from section 3 of https://www.w3.org/TR/xml-c14n, less
+    // what needs a DTD.
+    #[test]
+    fn c14n_outside_document_element() {
+        let doc = Node::Element(
+            QualName::new(None, ns!(), LocalName::from("#document")),
+            vec![],
+            vec![
+                Node::Pi(
+                    "xml-stylesheet",
+                    "href=\"doc.xsl\"\n   type=\"text/xsl\"   ",
+                ),
+                Node::Text("\n\n"),
+                Node::Element(
+                    qual("doc"),
+                    vec![],
+                    vec![Node::Text("Hello, world!"), Node::Comment(" Comment 1 ")],
+                ),
+                Node::Text("\n\n"),
+                Node::Pi("pi-without-data", ""),
+                Node::Text("\n\n"),
+                Node::Comment(" Comment 2 "),
+                Node::Text("\n\n"),
+                Node::Comment(" Comment 3 "),
+            ],
+        );
+        let canonical = |strip_comments| {
+            let opts = SerializeOpts {
+                traversal_scope: TraversalScope::ChildrenOnly(None),
+                xml_declaration: Some(XmlDeclaration::default()),
+                doctype: Some(DoctypeDeclaration::new("doc")),
+                strip_comments: strip_comments,
+                ..SerializeOpts::canonical()
+            };
+            let mut out = vec![];
+            serialize_document(&mut out, &doc, opts).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            canonical(false),
+            concat!(
+                "<?xml-stylesheet href=\"doc.xsl\"\n   type=\"text/xsl\"   ?>\n",
+                "<doc>Hello, world!<!-- Comment 1 --></doc>\n",
+                "<?pi-without-data?>\n",
+                "<!-- Comment 2 -->\n",
+                "<!-- Comment 3 -->"
+            )
+        );
+        assert_eq!(
+            canonical(true),
+            concat!(
+                "<?xml-stylesheet href=\"doc.xsl\"\n   type=\"text/xsl\"   ?>\n",
+                "<doc>Hello, world!</doc>\n",
+                "<?pi-without-data?>"
+            )
+        );
+    }
+
+    #[test]
+    fn c14n_start_and_end_tags() {
+        let w3 = "http://www.w3.org";
+        let ietf = "http://www.ietf.org";
+        let xmlns = |value| (QualName::new(None, ns!(xmlns), local_name!("xmlns")), value);
+        let xmlns_a = |value| (prefixed("xmlns", &ns!(xmlns), "a"), value);
+        let elem = |ns: &str, local: &str, attrs, children| {
+            Node::Element(
+                QualName::new(None, Namespace::from(ns), LocalName::from(local)),
+                attrs,
+                children,
+            )
+        };
+        let doc = elem(
+            "",
+            "doc",
+            vec![],
+            vec![
+                elem("", "e1", vec![], vec![]),
+                elem(
+                    "",
+                    "e3",
+                    vec![(qual("name"), "elem3"), (qual("id"), "elem3")],
+                    vec![],
+                ),
+                elem(
+                    "http://example.org",
+                    "e5",
+                    vec![
+                        (prefixed("a", w3, "attr"), "out"),
+                        (prefixed("b", ietf, "attr"), "sorted"),
+                        (qual("attr2"), "all"),
+                        (qual("attr"), "I'm"),
+                        (prefixed("xmlns", &ns!(xmlns), "b"), ietf),
+                        xmlns_a(w3),
+                        xmlns("http://example.org"),
+                    ],
+                    vec![],
+                ),
+                elem(
+                    "",
+                    "e6",
+                    vec![xmlns(""), xmlns_a(w3)],
+                    vec![elem(
+                        ietf,
+                        "e7",
+                        vec![xmlns(ietf)],
+                        vec![elem(
+                            "",
+                            "e8",
+                            vec![xmlns(""), xmlns_a(w3)],
+                            vec![elem("", "e9", vec![xmlns(""), xmlns_a(ietf)], vec![])],
+                        )],
+                    )],
+                ),
+            ],
+        );
+        let opts = SerializeOpts {
+            traversal_scope: TraversalScope::IncludeNode,
+            ..SerializeOpts::canonical()
+        };
+        assert_eq!(
+            serialize_to_string(&doc, opts).unwrap(),
+            concat!(
+                r#"<doc><e1></e1><e3 id="elem3" name="elem3"></e3>"#,
+                r#"<e5 xmlns="http://example.org" xmlns:a="http://www.w3.org" "#,
+                r#"xmlns:b="http://www.ietf.org" attr="I'm" attr2="all" b:attr="sorted" "#,
+                r#"a:attr="out"></e5>"#,
+                r#"<e6 xmlns:a="http://www.w3.org"><e7 xmlns="http://www.ietf.org">"#,
+                r#"<e8 xmlns=""><e9 xmlns:a="http://www.ietf.org"></e9></e8></e7></e6></doc>"#
+            )
+        );
+    }
+
+    #[test]
+    fn c14n_text() {
+        let mut ser = XmlSerializer::new(vec![], SerializeOpts::canonical());
+        start(&mut ser, qual("doc"), &[(qual("a"), "1\r\n2")]);
+        ser.write_cdata("a < b").unwrap();
+        ser.write_text("x\r\ny > z").unwrap();
+        ser.end_elem(qual("doc")).unwrap();
+        assert_eq!(
+            output(ser),
+            "<doc a=\"1&#xD;&#xA;2\">a &lt; bx&#xD;\ny &gt; z</doc>"
+        );
+    }
+
+    #[test]
+    fn duplicate_attributes_rejected_when_well_formed() {
+        let attrs = [
+            (prefixed("a", "foo", "id"), "1"),
+            (qual("id"), "2"),
+            (prefixed("b", "foo", "id"), "3"),
+        ];
+        let mut ser = XmlSerializer::new(
+            Vec::new(),
+            SerializeOpts {
+                require_well_formed: true,
+                ..opts()
+            },
+        );
+        let err = ser
+            .start_elem(qual("x"), attrs.iter().map(|&(ref n, v)| (n, v)))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "attribute '{foo}id' is given more than once"
+        );
+        assert_eq!(
+            error(err),
+            SerializeError::DuplicateAttribute(attrs[2].0.clone())
+        );
+        assert_eq!(output(ser), "");
+
+        let mut ser = XmlSerializer::new(Vec::new(), opts());
+        start(&mut ser, qual("x"), &attrs[..2]);
+        assert_eq!(output(ser), "<x xmlns:a=\"foo\" a:id=\"1\" id=\"2\">");
+    }
+
+    #[test]
+    fn default_namespace_undeclaration_can_be_forbidden() {
+        let outer = QualName::new(None, "foo".into(), "x".into());
+        for &allow in [true, false].iter() {
+            let mut ser = XmlSerializer::new(
+                Vec::new(),
+                SerializeOpts {
+                    allow_default_ns_undeclaration: allow,
+                    ..opts()
+                },
+            );
+            start(&mut ser, outer.clone(), &[]);
+            let result = ser.start_elem(qual("y"), vec![].into_iter());
+            if allow {
+                result.unwrap();
+                assert_eq!(output(ser), "<x xmlns=\"foo\"><y xmlns=\"\">");
+            } else {
+                assert_eq!(
+                    error(result.unwrap_err()),
+                    SerializeError::CannotUndeclareDefaultNamespace(qual("y"))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn element_hook_sees_every_element() {
+        use std::sync::Mutex;
+
+        let tree = Node::Element(
+            qual("a"),
+            vec![],
+            vec![
+                Node::Element(
+                    qual("b"),
+                    vec![],
+                    vec![Node::Element(qual("c"), vec![], vec![])],
+                ),
+                Node::Text("t"),
+                Node::Element(qual("d"), vec![], vec![]),
+            ],
+        );
+        let seen = Arc::new(Mutex::new(vec![]));
+        let hook_seen = seen.clone();
+        let opts = SerializeOpts {
+            on_element: Some(Arc::new(move |name: &QualName, depth| {
+                hook_seen
+                    .lock()
+                    .unwrap()
+                    .push((name.local.to_string(), depth))
+            })),
+            ..opts()
+        };
+        serialize_to_string(&tree, opts).unwrap();
+        let seen = seen.lock().unwrap();
+        assert_eq!(
+            *seen,
+            vec![
+                ("a".to_owned(), 0),
+                ("b".to_owned(), 1),
+                ("c".to_owned(), 2),
+                ("d".to_owned(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn comment_with_nul_is_rejected() {
+        let strict = SerializeOpts {
+            require_well_formed: true,
+            ..opts()
+        };
+        let mut ser = XmlSerializer::new(vec![], strict);
+        let err = ser.write_comment("a\0b").unwrap_err();
+        assert_eq!(
+            error(err),
+            SerializeError::NotXmlChar {
+                character: '\0',
+                index: 1,
+                attribute: None,
+                element: None,
+            }
+        );
+
+        let mut ser = XmlSerializer::new(vec![], opts());
+        ser.write_comment("a\0b").unwrap();
+    }
+
+    #[test]
+    fn invalid_element_names_are_rejected() {
+        let strict = SerializeOpts {
+            require_well_formed: true,
+            ..opts()
+        };
+        for name in &[
+            qual("1a"),
+            qual("a b"),
+            qual("a:b"),
+            prefixed("-p", "urn:p", "a"),
+            prefixed("p:q", "urn:p", "a"),
+        ] {
+            let mut ser = XmlSerializer::new(vec![], strict.clone());
+            let err = ser.start_elem(name.clone(), iter::empty()).unwrap_err();
+            assert_eq!(error(err), SerializeError::InvalidName(name.clone()));
+        }
+        let mut ser = XmlSerializer::new(vec![], strict);
+        start(&mut ser, prefixed("p", "urn:p", "a.b-c"), &[]);
+    }
+
+    #[test]
+    fn standalone_declaration() {
+        let declaration = |standalone, force_standalone| {
+            let mut ser = XmlSerializer::new(vec![], opts());
+            ser.write_xml_declaration(&XmlDeclaration {
+                encoding: Some("UTF-8".to_owned()),
+                standalone: standalone,
+                force_standalone: force_standalone,
+                ..Default::default()
+            })
+            .unwrap();
+            output(ser)
+        };
+        assert_eq!(
+            declaration(Some(true), false),
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#
+        );
+        assert_eq!(
+            declaration(Some(false), false),
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#
+        );
+        assert_eq!(
+            declaration(Some(false), true),
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>"#
+        );
+        assert_eq!(
+            declaration(None, true),
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#
+        );
+    }
+
+    #[test]
+    fn serialize_writes_declaration_for_whole_node() {
+        let doc = Node::Element(qual("doc"), vec![], vec![Node::Text("x")]);
+        let with = |decl: XmlDeclaration, traversal_scope| {
+            let opts = SerializeOpts {
+                traversal_scope: traversal_scope,
+                xml_declaration: Some(decl),
+                ..Default::default()
+            };
+            serialize_to_string(&doc, opts).unwrap()
+        };
+        assert_eq!(
+            with(Default::default(), TraversalScope::IncludeNode),
+            r#"<?xml version="1.0"?><doc>x</doc>"#
+        );
+        assert_eq!(
+            with(
+                XmlDeclaration {
+                    version: "1.1".to_owned(),
+                    encoding: Some("UTF-8".to_owned()),
+                    ..Default::default()
+                },
+                TraversalScope::IncludeNode
+            ),
+            r#"<?xml version="1.1" encoding="UTF-8"?><doc>x</doc>"#
+        );
+        assert_eq!(
+            with(
+                XmlDeclaration {
+                    standalone: Some(true),
+                    ..Default::default()
+                },
+                TraversalScope::IncludeNode
+            ),
+            r#"<?xml version="1.0" standalone="yes"?><doc>x</doc>"#
+        );
+        assert_eq!(
+            with(Default::default(), TraversalScope::ChildrenOnly(None)),
+            "x"
+        );
+        assert_eq!(serialize_to_string(&doc, opts()).unwrap(), "<doc>x</doc>");
+    }
+
+    #[test]
+    fn markup_declaration() {
+        let strict = SerializeOpts {
+            require_well_formed: true,
+            ..opts()
+        };
+        let mut ser = XmlSerializer::new(vec![], strict.clone());
+        ser.write_markup_declaration(r#"DOCTYPE doc [<!ENTITY copy "&#169;"> <!ENTITY q '>'>]"#)
+            .unwrap();
+        assert_eq!(
+            output(ser),
+            r#"<!DOCTYPE doc [<!ENTITY copy "&#169;"> <!ENTITY q '>'>]>"#
+        );
+
+        for text in &["ENTITY a \"b", "DOCTYPE doc [", "ATTLIST a>"] {
+            let mut ser = XmlSerializer::new(vec![], strict.clone());
+            let err = ser.write_markup_declaration(text).unwrap_err();
+            assert_eq!(error(err), SerializeError::UnbalancedDeclaration);
+        }
+    }
+
+    #[test]
+    fn namespaced_attribute_prefixes() {
+        let svg = || prefixed("s", "http://www.w3.org/2000/svg", "svg");
+        let attrs = || {
+            vec![
+                (
+                    QualName::new(
+                        None,
+                        Namespace::from("http://www.w3.org/2000/svg"),
+                        local_name!("x"),
+                    ),
+                    "1",
+                ),
+                (prefixed("t", "http://www.w3.org/2000/svg", "y"), "2"),
+                (QualName::new(None, ns!(xml), local_name!("lang")), "en"),
+            ]
+        };
+
+        let mut ser = XmlSerializer::new(vec![], opts());
+        start(&mut ser, svg(), &attrs());
+        assert_eq!(
+            output(ser),
+            concat!(
+                r#"<s:svg xmlns:ns1="http://www.w3.org/2000/svg" xmlns:s="http://www.w3.org/2000/svg" "#,
+                r#"xmlns:t="http://www.w3.org/2000/svg" ns1:x="1" t:y="2" xml:lang="en">"#
+            )
+        );
+
+        let minimized = SerializeOpts {
+            minimize_attr_prefixes: true,
+            ..opts()
+        };
+        let mut ser = XmlSerializer::new(vec![], minimized);
+        start(&mut ser, svg(), &attrs());
+        assert_eq!(
+            output(ser),
+            r#"<s:svg xmlns:s="http://www.w3.org/2000/svg" s:x="1" s:y="2" xml:lang="en">"#
+        );
+    }
+
+    #[test]
+    fn inherited_prefixes_are_picked_in_prefix_order() {
+        let minimized = SerializeOpts {
+            minimize_attr_prefixes: true,
+            ..opts()
+        };
+        let mut ser = XmlSerializer::new(vec![], minimized);
+        ser.push_namespace_scope(&[
+            (Some(Prefix::from("z")), Namespace::from("urn:shared")),
+            (Some(Prefix::from("b")), Namespace::from("urn:shared")),
+            (Some(Prefix::from("b")), Namespace::from("urn:b")),
+            (Some(Prefix::from("m")), Namespace::from("urn:shared")),
+        ])
+        .unwrap();
+        start(
+            &mut ser,
+            qual("e"),
+            &[
+                (prefixed("p", "urn:shared", "x"), "1"),
+                (prefixed("p", "urn:b", "y"), "2"),
+            ],
+        );
+        assert_eq!(output(ser), r#"<e m:x="1" b:y="2">"#);
+    }
+
+    #[test]
+    fn framed_document() {
+        let tree = Node::Element(qual("a"), vec![], vec![Node::Text("x & y")]);
+        let mut ser = XmlSerializer::new(FramedWriter::new(vec![0xff]), opts());
+        tree.serialize(&mut ser, TraversalScope::IncludeNode)
+            .unwrap();
+        let frame = ser.into_inner().unwrap().finish().unwrap();
+
+        assert_eq!(frame[0], 0xff);
+        let mut len = [0; 4];
+        len.copy_from_slice(&frame[1..5]);
+        let len = u32::from_be_bytes(len) as usize;
+        assert_eq!(len, frame.len() - 5);
+        assert_eq!(&frame[5..], &b"<a>x &amp; y</a>"[..]);
+    }
+
+    #[test]
+    fn xmlns_element_prefix() {
+        let tree = Node::Element(
+            prefixed("xmlns", "urn:x", "a"),
+            vec![],
+            vec![Node::Element(qual("b"), vec![], vec![])],
+        );
+        assert_eq!(
+            serialize_to_string(&tree, opts()).unwrap(),
+            r#"<a xmlns="urn:x"><b xmlns=""></b></a>"#
+        );
+
+        let strict = SerializeOpts {
+            require_well_formed: true,
+            ..opts()
+        };
+        let err = serialize_to_string(&tree, strict).unwrap_err();
+        assert_eq!(
+            error(err),
+            SerializeError::ReservedPrefix(prefixed("xmlns", "urn:x", "a"))
+        );
+    }
+
+    #[test]
+    fn boolean_attribute_styles() {
+        let serialize_with = |style| {
+            let opts = SerializeOpts {
+                boolean_attributes: vec![local_name!("disabled")].into_iter().collect(),
+                boolean_attribute_style: style,
+                ..opts()
+            };
+            let mut ser = XmlSerializer::new(vec![], opts);
+            start(
+                &mut ser,
+                qual("input"),
+                &[(qual("disabled"), ""), (qual("value"), "")],
+            );
+            output(ser)
+        };
+        assert_eq!(
+            serialize_with(BooleanAttributeStyle::Value),
+            r#"<input disabled="" value="">"#
+        );
+        assert_eq!(
+            serialize_with(BooleanAttributeStyle::Empty),
+            r#"<input disabled="" value="">"#
+        );
+        assert_eq!(
+            serialize_with(BooleanAttributeStyle::Name),
+            r#"<input disabled="disabled" value="">"#
+        );
+        assert_eq!(
+            serialize_with(BooleanAttributeStyle::Minimized),
+            r#"<input disabled value="">"#
+        );
+    }
+
+    #[test]
+    fn whole_document() {
+        let tree = Node::Element(qual("doc"), vec![], vec![Node::Text("hi")]);
+        let opts = SerializeOpts {
+            require_well_formed: true,
+            xml_declaration: Some(XmlDeclaration {
+                encoding: Some("UTF-8".to_owned()),
+                ..Default::default()
+            }),
+            doctype: Some(DoctypeDeclaration::new("doc")),
+            ..opts()
+        };
+        let mut out = vec![];
+        serialize_document(&mut out, &tree, opts).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"<?xml version="1.0" encoding="UTF-8"?><!DOCTYPE doc><doc>hi</doc>"#
+        );
+    }
+
+    #[test]
+    fn whole_document_with_doctype_ids() {
+        let tree = Node::Element(qual("doc"), vec![], vec![]);
+        let opts = SerializeOpts {
+            require_well_formed: true,
+            doctype: Some(DoctypeDeclaration {
+                public_id: Some("-//Example//DTD Doc//EN".to_owned()),
+                system_id: Some("doc.dtd".to_owned()),
+                ..DoctypeDeclaration::new("doc")
+            }),
+            ..opts()
+        };
+        let mut out = vec![];
+        serialize_document(&mut out, &tree, opts).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"<!DOCTYPE doc PUBLIC "-//Example//DTD Doc//EN" "doc.dtd"><doc></doc>"#
+        );
+    }
+
+    #[test]
+    fn second_doctype_is_rejected() {
+        let document = Node::Element(
+            qual("#document"),
+            vec![],
+            vec![
+                Node::Doctype("doc"),
+                Node::Element(qual("doc"), vec![], vec![]),
+            ],
+        );
+        let with_doctype = |require_well_formed| SerializeOpts {
+            require_well_formed: require_well_formed,
+            traversal_scope: TraversalScope::ChildrenOnly(None),
+            doctype: Some(DoctypeDeclaration::new("doc")),
+            ..opts()
+        };
+        let err = serialize_document(vec![], &document, with_doctype(true)).unwrap_err();
+        assert_eq!(error(err), SerializeError::MultipleDoctypes);
+
+        let mut out = vec![];
+        serialize_document(&mut out, &document, with_doctype(false)).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "<!DOCTYPE doc><!DOCTYPE doc><doc></doc>"
+        );
+
+        let opts = SerializeOpts {
+            doctype: None,
+            ..with_doctype(true)
+        };
+        let mut out = vec![];
+        serialize_document(&mut out, &document, opts).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "<!DOCTYPE doc><doc></doc>");
+    }
+
+    #[test]
+    fn comment_fixing() {
+        let strict = SerializeOpts {
+            require_well_formed: true,
+            ..opts()
+        };
+        for text in &["x-", "a--b"] {
+            let mut ser = XmlSerializer::new(vec![], strict.clone());
+            let err = ser.write_comment(text).unwrap_err();
+            assert_eq!(error(err), SerializeError::InvalidComment);
+        }
+
+        let comment = |fallback, text| {
+            let opts = SerializeOpts {
+                comment_fallback: fallback,
+                ..strict.clone()
+            };
+            let mut ser = XmlSerializer::new(vec![], opts);
+            ser.write_comment(text).unwrap();
+            output(ser)
+        };
+        assert_eq!(comment(CommentFallback::Split, "x-"), "<!--x- -->");
+        assert_eq!(comment(CommentFallback::Split, "a--b"), "<!--a- -b-->");
+        assert_eq!(comment(CommentFallback::Split, "a---"), "<!--a- - - -->");
+        assert_eq!(comment(CommentFallback::Split, "a-b"), "<!--a-b-->");
+        assert_eq!(comment(CommentFallback::Strip, "x-"), "<!--x-->");
+        assert_eq!(comment(CommentFallback::Strip, "a--b"), "<!--ab-->");
+        assert_eq!(comment(CommentFallback::Strip, "a---b--"), "<!--ab-->");
+        assert_eq!(comment(CommentFallback::Strip, "a-b"), "<!--a-b-->");
+
+        #[allow(deprecated)]
+        let legacy = SerializeOpts {
+            auto_fix_comments: true,
+            ..strict.clone()
+        };
+        let mut ser = XmlSerializer::new(vec![], legacy);
+        ser.write_comment("a--b").unwrap();
+        assert_eq!(output(ser), "<!--a- -b-->");
+
+        let mut ser = XmlSerializer::new(vec![], opts());
+        ser.write_comment("a--b").unwrap();
+        assert_eq!(output(ser), "<!--a--b-->");
+    }
+
+    #[test]
+    fn stripping_comments_and_pis() {
+        let tree = Node::Element(
+            qual("a"),
+            vec![],
+            vec![Node::Comment("c"), Node::Text("x"), Node::Pi("pi", "data")],
+        );
+        let with = |strip_comments, strip_processing_instructions| {
+            let opts = SerializeOpts {
+                strip_comments: strip_comments,
+                strip_processing_instructions: strip_processing_instructions,
+                ..opts()
+            };
+            serialize_to_string(&tree, opts).unwrap()
+        };
+        assert_eq!(with(false, false), "<a><!--c-->x<?pi data?></a>");
+        assert_eq!(with(true, false), "<a>x<?pi data?></a>");
+        assert_eq!(with(false, true), "<a><!--c-->x</a>");
+        assert_eq!(with(true, true), "<a>x</a>");
+    }
+
+    #[test]
+    fn wrapped_fragment() {
+        let fragment = Node::Element(
+            qual("ignored"),
+            vec![],
+            vec![
+                Node::Element(prefixed("f", "urn:f", "item"), vec![], vec![]),
+                Node::Element(
+                    prefixed("f", "urn:f", "item"),
+                    vec![],
+                    vec![Node::Text("2")],
+                ),
+            ],
+        );
+        let root = QualName::new(None, Namespace::from("urn:root"), local_name!("root"));
+        let root_attrs = vec![
+            Attribute {
+                name: QualName::new(
+                    Some(namespace_prefix!("xmlns")),
+                    ns!(xmlns),
+                    LocalName::from("f"),
+                ),
+                value: "urn:f".into(),
+            },
+            Attribute {
+                name: qual("version"),
+                value: "1".into(),
+            },
+        ];
+        let mut out = vec![];
+        serialize_wrapped(&mut out, &fragment, root, &root_attrs, Default::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                r#"<root xmlns="urn:root" xmlns:f="urn:f" version="1">"#,
+                "<f:item></f:item><f:item>2</f:item></root>"
+            )
+        );
+    }
+
+    #[test]
+    fn allowed_names() {
+        let opts = SerializeOpts {
+            allowed_elements: Some(vec![qual("p"), qual("a")].into_iter().collect()),
+            allowed_attributes: Some(vec![qual("href")].into_iter().collect()),
+            ..opts()
+        };
+        let allowed = Node::Element(
+            qual("p"),
+            vec![],
+            vec![Node::Element(qual("a"), vec![(qual("href"), "/")], vec![])],
+        );
+        assert_eq!(
+            serialize_to_string(&allowed, opts.clone()).unwrap(),
+            r#"<p><a href="/"></a></p>"#
+        );
+
+        let element = Node::Element(
+            qual("p"),
+            vec![],
+            vec![Node::Element(qual("script"), vec![], vec![])],
+        );
+        let err = serialize_to_string(&element, opts.clone()).unwrap_err();
+        assert_eq!(err.to_string(), "'{}script' is not allowed");
+        assert_eq!(error(err), SerializeError::DisallowedName(qual("script")));
+
+        let attribute = Node::Element(qual("a"), vec![(qual("onclick"), "x()")], vec![]);
+        let err = serialize_to_string(&attribute, opts).unwrap_err();
+        assert_eq!(error(err), SerializeError::DisallowedName(qual("onclick")));
+    }
+
+    #[test]
+    fn references_outside_charset() {
+        let tree = Node::Element(
+            qual("p"),
+            vec![(qual("title"), "naïve")],
+            vec![Node::Text("café & 😁"), Node::Comment("é")],
+        );
+        let opts = SerializeOpts {
+            reference_charset: Some(CharsetMask::Ascii),
+            ..opts()
+        };
+        assert_eq!(
+            serialize_to_string(&tree, opts).unwrap(),
+            r#"<p title="na&#xEF;ve">caf&#xE9; &amp; &#x1F601;<!--é--></p>"#
+        );
+        assert_eq!(
+            serialize_to_string(&tree, self::opts()).unwrap(),
+            r#"<p title="naïve">café &amp; 😁<!--é--></p>"#
+        );
+    }
+
+    #[test]
+    fn injected_namespaces() {
+        let tree = Node::Element(
+            QualName::new(None, Namespace::from("urn:d"), LocalName::from("doc")),
+            vec![(qual("id"), "1")],
+            vec![Node::Element(
+                prefixed("p", "urn:p", "item"),
+                vec![],
+                vec![],
+            )],
+        );
+        let decls = vec![
+            (None, Namespace::from("urn:d")),
+            (Some(Prefix::from("p")), Namespace::from("urn:p")),
+        ];
+        assert_eq!(
+            serialize_to_string(&WithNamespaces(&tree, decls), opts()).unwrap(),
+            r#"<doc xmlns="urn:d" xmlns:p="urn:p" id="1"><p:item></p:item></doc>"#
+        );
+    }
+
+    #[test]
+    fn injected_namespaces_defer_to_own_declarations() {
+        let xmlns = |prefix| prefixed("xmlns", &ns!(xmlns), prefix);
+        let tree = Node::Element(
+            prefixed("a", "urn:own", "doc"),
+            vec![(xmlns("a"), "urn:own")],
+            vec![],
+        );
+        let decls = vec![
+            (Some(Prefix::from("a")), Namespace::from("urn:injected")),
+            (Some(Prefix::from("b")), Namespace::from("urn:b")),
+        ];
+        let expected = r#"<a:doc xmlns:a="urn:own" xmlns:b="urn:b"></a:doc>"#;
+        for &require_well_formed in &[false, true] {
+            let opts = SerializeOpts {
+                require_well_formed: require_well_formed,
+                ..opts()
+            };
+            let decls = decls.clone();
+            assert_eq!(
+                serialize_to_string(&WithNamespaces(&tree, decls), opts).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_attribute_char_names_the_attribute() {
+        let strict = SerializeOpts {
+            require_well_formed: true,
+            ..opts()
+        };
+        let tree = Node::Element(
+            qual("a"),
+            vec![
+                (qual("x"), "fine"),
+                (qual("y"), "ab\u{B}c"),
+                (qual("z"), "\0"),
+            ],
+            vec![],
+        );
+        let err = serialize_to_string(&tree, strict).unwrap_err();
+        assert_eq!(
+            error(err),
+            SerializeError::NotXmlChar {
+                character: '\u{B}',
+                index: 2,
+                attribute: Some(qual("y")),
+                element: Some(qual("a")),
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_char_errors_name_their_context() {
+        let strict = SerializeOpts {
+            require_well_formed: true,
+            ..opts()
+        };
+        let tree = Node::Element(
+            prefixed("p", "urn:p", "foo"),
+            vec![(qual("id"), "a\0")],
+            vec![],
+        );
+        let err = serialize_to_string(&tree, strict.clone()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "'\\0' at 1 is not allowed in an XML document (in attribute 'id' of element 'p:foo')"
+        );
+
+        let tree = Node::Element(
+            qual("a"),
+            vec![],
+            vec![Node::Element(qual("b"), vec![], vec![Node::Text("x\u{1}")])],
+        );
+        let err = serialize_to_string(&tree, strict.clone()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "'\\u{1}' at 1 is not allowed in an XML document (in element 'b')"
+        );
+
+        let coalesce = SerializeOpts {
+            coalesce_text: true,
+            ..strict
+        };
+        let err = serialize_to_string(&tree, coalesce).unwrap_err();
+        assert_eq!(
+            error(err),
+            SerializeError::NotXmlChar {
+                character: '\u{1}',
+                index: 1,
+                attribute: None,
+                element: Some(qual("b")),
+            }
+        );
+    }
+
+    #[test]
+    fn well_formed_policies() {
+        let tree = Node::Element(
+            qual("a"),
+            vec![(qual("x"), "1\u{1}2")],
+            vec![Node::Text("b\0c\u{1}"), Node::Text("d")],
+        );
+        let policy = |policy| SerializeOpts {
+            require_well_formed: true,
+            well_formed_policy: policy,
+            ..opts()
+        };
+
+        let err = serialize_to_string(&tree, policy(WellFormedPolicy::Error)).unwrap_err();
+        assert_eq!(
+            error(err),
+            SerializeError::NotXmlChar {
+                character: '\u{1}',
+                index: 1,
+                attribute: Some(qual("x")),
+                element: Some(qual("a")),
+            }
+        );
+        assert_eq!(
+            serialize_to_string(&tree, policy(WellFormedPolicy::Drop)).unwrap(),
+            r#"<a x="12">bcd</a>"#
+        );
+        assert_eq!(
+            serialize_to_string(&tree, policy(WellFormedPolicy::ReplaceWith('\u{FFFD}'))).unwrap(),
+            "<a x=\"1\u{FFFD}2\">b\u{FFFD}c\u{FFFD}d</a>"
+        );
+        let coalesce = SerializeOpts {
+            coalesce_text: true,
+            ..policy(WellFormedPolicy::ReplaceWith('?'))
+        };
+        assert_eq!(
+            serialize_to_string(&tree, coalesce).unwrap(),
+            r#"<a x="1?2">b?c?d</a>"#
+        );
+
+        // Without require_well_formed the policy is not used.
+        let lenient = SerializeOpts {
+            well_formed_policy: WellFormedPolicy::Drop,
+            ..opts()
+        };
+        assert_eq!(
+            serialize_to_string(&tree, lenient).unwrap(),
+            "<a x=\"1\u{1}2\">b\0c\u{1}d</a>"
+        );
+    }
+
+    #[test]
+    fn tendril_output() {
+        let tree = Node::Element(
+            prefixed("p", "urn:p", "a"),
+            vec![(qual("title"), "\"naïve\"")],
+            vec![Node::Text("x < y"), Node::Comment("c")],
+        );
+        let tendril = serialize_to_tendril(&tree, opts()).unwrap();
+        assert_eq!(&*tendril, &*serialize_to_string(&tree, opts()).unwrap());
+    }
+
+    #[test]
+    fn trimming_respects_xml_space() {
+        let space = |value| (QualName::new(None, ns!(xml), local_name!("space")), value);
+        let tree = Node::Element(
+            qual("doc"),
+            vec![],
+            vec![
+                Node::Text("\n  "),
+                Node::Element(qual("a"), vec![], vec![Node::Text("  x  ")]),
+                Node::Element(
+                    qual("pre"),
+                    vec![space("preserve")],
+                    vec![
+                        Node::Text("  y  "),
+                        Node::Element(qual("b"), vec![], vec![Node::Text(" z ")]),
+                        Node::Element(qual("c"), vec![space("default")], vec![Node::Text(" w ")]),
+                    ],
+                ),
+                Node::Text("\n"),
+            ],
+        );
+        let expected = concat!(
+            r#"<doc><a>x</a><pre xml:space="preserve">  y  <b> z </b>"#,
+            r#"<c xml:space="default">w</c></pre></doc>"#
+        );
+        for &coalesce_text in &[false, true] {
+            let opts = SerializeOpts {
+                trim_text: true,
+                coalesce_text: coalesce_text,
+                ..opts()
+            };
+            assert_eq!(serialize_to_string(&tree, opts).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn text_whitespace_policies() {
+        let space = |value| (QualName::new(None, ns!(xml), local_name!("space")), value);
+        let tree = Node::Element(
+            qual("doc"),
+            vec![],
+            vec![
+                Node::Text("\n  "),
+                Node::Element(qual("a"), vec![], vec![Node::Text(" x \t\r\n y  ")]),
+                Node::Element(
+                    qual("pre"),
+                    vec![space("preserve")],
+                    vec![Node::Element(
+                        qual("b"),
+                        vec![],
+                        vec![Node::Text(" z \n z ")],
+                    )],
+                ),
+            ],
+        );
+        let render = |policy, coalesce_text| {
+            let opts = SerializeOpts {
+                text_whitespace: policy,
+                coalesce_text: coalesce_text,
+                ..opts()
+            };
+            serialize_to_string(&tree, opts).unwrap()
+        };
+        let pre = "<pre xml:space=\"preserve\"><b> z \n z </b></pre></doc>";
+        for &coalesce_text in &[false, true] {
+            assert_eq!(
+                render(WhitespacePolicy::Preserve, coalesce_text),
+                format!("<doc>\n  <a> x \t\r\n y  </a>{}", pre)
+            );
+            assert_eq!(
+                render(WhitespacePolicy::Trim, coalesce_text),
+                format!("<doc><a>x \t\r\n y</a>{}", pre)
+            );
+            assert_eq!(
+                render(WhitespacePolicy::Collapse, coalesce_text),
+                format!("<doc> <a> x y </a>{}", pre)
+            );
+        }
+    }
+
+    #[test]
+    fn standalone_attribute_value() {
+        let value = r#"say "hi" & 'bye'"#;
+        assert_eq!(
+            escape_attr_value(value, QuoteStyle::Double, &opts()).unwrap(),
+            "say &quot;hi&quot; &amp; 'bye'"
+        );
+        assert_eq!(
+            escape_attr_value(value, QuoteStyle::Single, &opts()).unwrap(),
+            r#"say "hi" &amp; &apos;bye&apos;"#
+        );
+    }
+
+    #[test]
+    fn redeclarations_are_deterministic() {
+        let render = || {
+            let mut ser = XmlSerializer::new(vec![], opts());
+            start(&mut ser, prefixed("p", "urn:a", "outer"), &[]);
+            start(
+                &mut ser,
+                qual("inner"),
+                &[
+                    (prefixed("p", "urn:b", "x"), "1"),
+                    (prefixed("q", "urn:a", "y"), "2"),
+                    (
+                        QualName::new(None, Namespace::from("urn:c"), LocalName::from("z")),
+                        "3",
+                    ),
+                    (
+                        QualName::new(None, Namespace::from("urn:d"), LocalName::from("w")),
+                        "4",
+                    ),
+                ],
+            );
+            output(ser)
+        };
+        let first = render();
+        assert_eq!(
+            first,
+            concat!(
+                r#"<p:outer xmlns:p="urn:a"><inner xmlns:ns1="urn:c" xmlns:ns2="urn:d" "#,
+                r#"xmlns:p="urn:b" xmlns:q="urn:a" p:x="1" q:y="2" ns1:z="3" ns2:w="4">"#
+            )
+        );
+        for _ in 0..100 {
+            assert_eq!(render(), first);
+        }
+    }
+
+    #[test]
+    fn plain_attribute_fast_path() {
+        let attrs = [
+            (qual("id"), "a&b"),
+            (prefixed("p", "urn:p", "x"), "1"),
+            (qual("class"), "\"q\" <t>"),
+            (
+                QualName::new(None, Namespace::from("urn:n"), LocalName::from("y")),
+                "2",
+            ),
+        ];
+        let render = |opts| {
+            let mut ser = XmlSerializer::new(vec![], opts);
+            start(&mut ser, qual("e"), &attrs);
+            output(ser)
+        };
+        // An unrelated boolean attribute forces every attribute through the
+        // general path.
+        let general = SerializeOpts {
+            boolean_attributes: vec![LocalName::from("unused")].into_iter().collect(),
+            ..opts()
+        };
+        assert_eq!(render(opts()), render(general));
+        assert_eq!(
+            render(opts()),
+            concat!(
+                r#"<e xmlns:ns1="urn:n" xmlns:p="urn:p" id="a&amp;b" p:x="1" "#,
+                r#"class="&quot;q&quot; &lt;t&gt;" ns1:y="2">"#
+            )
+        );
+    }
+
+    #[test]
+    fn pre_opened_namespace_scope() {
+        let mut ser = XmlSerializer::new(vec![], opts());
+        ser.push_namespace_scope(&[
+            (None, Namespace::from("urn:d")),
+            (Some(Prefix::from("p")), Namespace::from("urn:p")),
+        ])
+        .unwrap();
+        let item = QualName::new(None, Namespace::from("urn:d"), LocalName::from("item"));
+        start(
+            &mut ser,
+            item.clone(),
+            &[(prefixed("p", "urn:p", "x"), "1")],
+        );
+        ser.end_elem(item).unwrap();
+        ser.pop_namespace_scope().unwrap();
+        start(&mut ser, prefixed("p", "urn:p", "after"), &[]);
+        assert_eq!(
+            output(ser),
+            r#"<item p:x="1"></item><p:after xmlns:p="urn:p">"#
+        );
+    }
+
+    #[test]
+    fn generated_prefixes_skip_bound_ones() {
+        let mut ser = XmlSerializer::new(vec![], opts());
+        ser.push_namespace_scope(&[(Some(Prefix::from("ns1")), Namespace::from("urn:one"))])
+            .unwrap();
+        start(
+            &mut ser,
+            qual("e"),
+            &[
+                (
+                    QualName::new(None, Namespace::from("urn:x"), local_name!("x")),
+                    "1",
+                ),
+                (prefixed("ns2", "urn:two", "y"), "2"),
+            ],
+        );
+        assert_eq!(
+            output(ser),
+            r#"<e xmlns:ns2="urn:two" xmlns:ns3="urn:x" ns3:x="1" ns2:y="2">"#
+        );
+    }
+
+    #[test]
+    fn generated_prefix_prefix() {
+        let opts = SerializeOpts {
+            generated_prefix_prefix: "g".to_owned(),
+            ..opts()
+        };
+        let mut ser = XmlSerializer::new(vec![], opts);
+        let attr = |ns: &str, local: &str| {
+            QualName::new(None, Namespace::from(ns), LocalName::from(local))
+        };
+        start(
+            &mut ser,
+            qual("e"),
+            &[(attr("urn:a", "x"), "1"), (attr("urn:b", "y"), "2")],
+        );
+        assert_eq!(
+            output(ser),
+            r#"<e xmlns:g1="urn:a" xmlns:g2="urn:b" g1:x="1" g2:y="2">"#
+        );
+
+        for &start in &["", "xml", "XMLns", "a:", "g1", "1g"] {
+            let invalid = SerializeOpts {
+                generated_prefix_prefix: start.to_owned(),
+                ..self::opts()
+            };
+            let mut ser = XmlSerializer::new(vec![], invalid);
+            let name = QualName::new(None, Namespace::from("urn:a"), LocalName::from("x"));
+            let err = ser
+                .start_elem(qual("e"), vec![(&name, "1")].into_iter())
+                .unwrap_err();
+            assert_eq!(
+                error(err),
+                SerializeError::InvalidGeneratedPrefix(start.to_owned())
+            );
+        }
+    }
+
+    #[test]
+    fn cdata_sections() {
+        let mut ser = XmlSerializer::new(vec![], opts());
+        start(&mut ser, qual("script"), &[]);
+        ser.write_text("a < b").unwrap();
+        ser.write_cdata(" if (a < b && c) ]] ").unwrap();
+        ser.end_elem(qual("script")).unwrap();
+        assert_eq!(
+            output(ser),
+            "<script>a &lt; b<![CDATA[ if (a < b && c) ]] ]]></script>"
+        );
+
+        let strict = SerializeOpts {
+            require_well_formed: true,
+            ..opts()
+        };
+        let mut ser = XmlSerializer::new(vec![], strict);
+        start(&mut ser, qual("a"), &[]);
+        assert_eq!(
+            error(ser.write_cdata("x]]>y").unwrap_err()),
+            SerializeError::InvalidCdata
+        );
+    }
+
+    #[test]
+    fn indentation() {
+        let doc = Node::Element(
+            qual("doc"),
+            vec![],
+            vec![
+                Node::Element(
+                    qual("list"),
+                    vec![],
+                    vec![
+                        Node::Element(qual("item"), vec![], vec![Node::Text("one")]),
+                        Node::Comment(" two "),
+                        Node::Element(qual("item"), vec![], vec![]),
+                    ],
+                ),
+                Node::Element(
+                    qual("p"),
+                    vec![],
+                    vec![
+                        Node::Text("some "),
+                        Node::Element(
+                            qual("b"),
+                            vec![],
+                            vec![Node::Element(qual("i"), vec![], vec![])],
+                        ),
+                        Node::Text(" text"),
+                    ],
+                ),
+            ],
+        );
+        assert_eq!(
+            serialize_to_string(&doc, opts()).unwrap(),
+            "<doc><list><item>one</item><!-- two --><item></item></list>\
+             <p>some <b><i></i></b> text</p></doc>"
+        );
+        let indented = |indent| {
+            let opts = SerializeOpts {
+                indent: Some(indent),
+                ..opts()
+            };
+            serialize_to_string(&doc, opts).unwrap()
+        };
+        assert_eq!(
+            indented(IndentStyle::Spaces(2)),
+            "<doc>\n  <list>\n    <item>one</item>\n    <!-- two -->\n    <item></item>\n  </list>\n  \
+             <p>some <b><i></i></b> text</p>\n</doc>"
+        );
+        assert_eq!(
+            indented(IndentStyle::Tab),
+            "<doc>\n\t<list>\n\t\t<item>one</item>\n\t\t<!-- two -->\n\t\t<item></item>\n\t</list>\n\t\
+             <p>some <b><i></i></b> text</p>\n</doc>"
+        );
+    }
+
+    #[test]
+    fn newline_styles() {
+        let doc = Node::Element(
+            qual("a"),
+            vec![],
+            vec![
+                Node::Element(qual("b"), vec![], vec![Node::Text("x\ny")]),
+                Node::Pi("pi", ""),
+            ],
+        );
+        let with = |newline| {
+            let opts = SerializeOpts {
+                indent: Some(IndentStyle::Spaces(1)),
+                newline: newline,
+                ..opts()
+            };
+            serialize_to_string(&doc, opts).unwrap()
+        };
+        assert_eq!(with(Newline::Lf), "<a>\n <b>x\ny</b>\n <?pi ?>\n</a>");
+        assert_eq!(
+            with(Newline::CrLf),
+            "<a>\r\n <b>x\ny</b>\r\n <?pi ?>\r\n</a>"
+        );
+        assert_eq!(with(Newline::Cr), "<a>\r <b>x\ny</b>\r <?pi ?>\r</a>");
+    }
+
+    #[test]
+    fn escaping_matches_per_char_reference() {
+        let reference = |text: &str, quote: Option<char>| {
+            let mut out = String::new();
+            for c in text.chars() {
+                match c {
+                    '&' => out.push_str("&amp;"),
+                    '<' => out.push_str("&lt;"),
+                    '>' => out.push_str("&gt;"),
+                    '"' if quote == Some('"') => out.push_str("&quot;"),
+                    '\'' if quote == Some('\'') => out.push_str("&apos;"),
+                    c => out.push(c),
+                }
+            }
+            out
+        };
+        let text = "plain <b>bold</b> & \"double\" 'single' ünïcödé 😁 a&&b <<>> end&";
+        for &(context, quote) in [
+            (EscapeContext::Text, None),
+            (EscapeContext::Attribute(QuoteStyle::Double), Some('"')),
+            (EscapeContext::Attribute(QuoteStyle::Single), Some('\'')),
+        ]
+        .iter()
+        {
+            for end in 0..=text.len() {
+                if !text.is_char_boundary(end) {
+                    continue;
+                }
+                let mut out = Vec::new();
+                write_escaped(&mut out, &text[..end], context, &Default::default()).unwrap();
+                assert_eq!(
+                    String::from_utf8(out).unwrap(),
+                    reference(&text[..end], quote)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reference_charsets_for_escape_modes() {
+        let text = "caf\u{E9}\u{7F}\u{85}\t";
+        let escaped = |reference_charset| {
+            let opts = SerializeOpts {
+                reference_charset: reference_charset,
+                ..opts()
+            };
+            let mut out = Vec::new();
+            write_escaped(&mut out, text, EscapeContext::Text, &opts).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(escaped(None), text);
+        assert_eq!(escaped(Some(CharsetMask::Ascii)), "caf&#xE9;\u{7F}&#x85;\t");
+        assert_eq!(
+            escaped(Some(CharsetMask::NonControl)),
+            "caf\u{E9}&#x7F;&#x85;\t"
+        );
+    }
+
+    #[test]
+    fn doctype_identifiers() {
+        let doctype = |public_id, system_id| {
+            let mut ser = XmlSerializer::new(vec![], opts());
+            ser.write_doctype_full("html", public_id, system_id)
+                .unwrap();
+            output(ser)
+        };
+        assert_eq!(doctype(None, None), "<!DOCTYPE html>");
+        assert_eq!(
+            doctype(None, Some("about:legacy-compat")),
+            r#"<!DOCTYPE html SYSTEM "about:legacy-compat">"#
+        );
+        assert_eq!(
+            doctype(Some("-//W3C//DTD XHTML 1.0 Strict//EN"), Some("strict.dtd")),
+            r#"<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Strict//EN" "strict.dtd">"#
+        );
+        assert_eq!(
+            doctype(None, Some(r#"say "hi".dtd"#)),
+            r#"<!DOCTYPE html SYSTEM 'say "hi".dtd'>"#
+        );
+
+        let strict = SerializeOpts {
+            require_well_formed: true,
+            ..opts()
+        };
+        let check = |public_id, system_id| {
+            let mut ser = XmlSerializer::new(vec![], strict.clone());
+            ser.write_doctype_full("html", public_id, system_id)
+                .map_err(error)
+        };
+        assert!(check(Some("-//A//B"), Some("b.dtd")).is_ok());
+        assert_eq!(
+            check(Some("caf\u{E9}"), Some("b.dtd")),
+            Err(SerializeError::InvalidPublicId)
+        );
+        assert_eq!(
+            check(Some("-//A//B"), None),
+            Err(SerializeError::InvalidPublicId)
+        );
+        assert_eq!(
+            check(None, Some("'\"")),
+            Err(SerializeError::InvalidSystemId)
+        );
+    }
+
+    #[test]
+    fn attribute_quote_styles() {
+        let quoted = |quote_style| {
+            let opts = SerializeOpts {
+                quote_style: quote_style,
+                ..opts()
+            };
+            let mut ser = XmlSerializer::new(vec![], opts);
+            start(
+                &mut ser,
+                prefixed("p", "urn:p", "a"),
+                &[(qual("title"), r#"say "hi" & 'bye'"#)],
+            );
+            output(ser)
+        };
+        assert_eq!(
+            quoted(QuoteStyle::Double),
+            r#"<p:a xmlns:p="urn:p" title="say &quot;hi&quot; &amp; 'bye'">"#
+        );
+        assert_eq!(
+            quoted(QuoteStyle::Single),
+            r#"<p:a xmlns:p='urn:p' title='say "hi" &amp; &apos;bye&apos;'>"#
+        );
+    }
+
+    #[test]
+    fn serialize_flushes_writer() {
+        struct Recorder {
+            data: Vec<u8>,
+            flushed: Vec<usize>,
+        }
+
+        impl Write for Recorder {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.data.write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                self.flushed.push(self.data.len());
+                Ok(())
+            }
+        }
+
+        let mut recorder = Recorder {
+            data: vec![],
+            flushed: vec![],
+        };
+        let doc = Node::Element(qual("a"), vec![], vec![Node::Text("x")]);
+        let coalesce = SerializeOpts {
+            coalesce_text: true,
+            ..opts()
+        };
+        serialize(&mut recorder, &doc, coalesce).unwrap();
+        assert_eq!(recorder.data, b"<a>x</a>");
+        assert_eq!(recorder.flushed, [8]);
+    }
+
+    #[test]
+    fn serialize_into_keeps_the_writer() {
+        let mut out = vec![];
+        let first = Node::Element(qual("a"), vec![], vec![Node::Text("1")]);
+        let second = Node::Element(qual("b"), vec![], vec![]);
+        serialize_into(&mut out, &first, opts()).unwrap();
+        out.push(b'\n');
+        serialize_into(&mut out, &second, opts()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "<a>1</a>\n<b></b>");
+    }
+
+    #[test]
+    fn end_elem_without_open_element() {
+        let mut ser = XmlSerializer::new(vec![], opts());
+        ser.end_elem(qual("a")).unwrap();
+        start(&mut ser, qual("b"), &[]);
+        ser.write_text("x").unwrap();
+        ser.end_elem(qual("b")).unwrap();
+        ser.end_elem(qual("c")).unwrap();
+        assert_eq!(output(ser), "</a><b>x</b></c>");
+    }
+
+    #[test]
+    fn unbalanced_pops_keep_the_base_scope() {
+        let mut ser = XmlSerializer::new(vec![], document_opts());
+        ser.pop_namespace_scope().unwrap();
+        ser.write_text(" ").unwrap();
+        start(&mut ser, qual("b"), &[]);
+        ser.end_elem(qual("b")).unwrap();
+        assert_eq!(output(ser), " <b></b>");
+    }
+
+    #[test]
+    fn xml_space_is_inherited() {
+        let space = |value| (QualName::new(None, ns!(xml), local_name!("space")), value);
+        let mut ser = XmlSerializer::new(vec![], opts());
+        assert!(!ser.preserve_space());
+        start(&mut ser, qual("doc"), &[space("default")]);
+        assert!(!ser.preserve_space());
+        start(&mut ser, qual("pre"), &[space("preserve")]);
+        assert!(ser.preserve_space());
+        start(&mut ser, qual("b"), &[]);
+        assert!(ser.preserve_space());
+        ser.push_namespace_scope(&[]).unwrap();
+        assert!(ser.preserve_space());
+        start(&mut ser, qual("c"), &[space("default")]);
+        assert!(!ser.preserve_space());
+        start(&mut ser, qual("d"), &[space("other")]);
+        assert!(!ser.preserve_space());
+        ser.end_elem(qual("d")).unwrap();
+        ser.end_elem(qual("c")).unwrap();
+        assert!(ser.preserve_space());
+        ser.pop_namespace_scope().unwrap();
+        ser.end_elem(qual("b")).unwrap();
+        ser.end_elem(qual("pre")).unwrap();
+        assert!(!ser.preserve_space());
+        ser.end_elem(qual("doc")).unwrap();
+        assert!(!ser.preserve_space());
+    }
+
+    #[test]
+    fn mismatched_end_tags() {
+        let strict = || SerializeOpts {
+            require_well_formed: true,
+            ..opts()
+        };
+        let mut ser = XmlSerializer::new(vec![], strict());
+        start(&mut ser, qual("a"), &[]);
+        start(&mut ser, prefixed("p", "urn:p", "b"), &[]);
+        for name in vec![qual("a"), qual("b"), prefixed("q", "urn:p", "b")] {
+            assert_eq!(
+                error(ser.end_elem(name.clone()).unwrap_err()),
+                SerializeError::MismatchedEndTag {
+                    expected: Some(prefixed("p", "urn:p", "b")),
+                    found: name,
+                }
+            );
+        }
+        ser.end_elem(prefixed("p", "urn:p", "b")).unwrap();
+        ser.end_elem(qual("a")).unwrap();
+        assert_eq!(
+            error(ser.end_elem(qual("a")).unwrap_err()),
+            SerializeError::MismatchedEndTag {
+                expected: None,
+                found: qual("a"),
+            }
+        );
+        assert_eq!(output(ser), r#"<a><p:b xmlns:p="urn:p"></p:b></a>"#);
+
+        let mut ser = XmlSerializer::new(vec![], strict());
+        start(&mut ser, qual("a"), &[]);
+        ser.push_namespace_scope(&[]).unwrap();
+        assert!(ser.end_elem(qual("a")).is_err());
+        ser.pop_namespace_scope().unwrap();
+        ser.end_elem(qual("a")).unwrap();
+    }
+
+    #[test]
+    fn position_counts_written_bytes() {
+        let mut ser = XmlSerializer::new(vec![], opts());
+        assert_eq!(ser.position(), 0);
+        start(&mut ser, prefixed("p", "urn:p", "a"), &[]);
+        assert_eq!(ser.position(), r#"<p:a xmlns:p="urn:p">"#.len());
+        ser.write_text("x & y").unwrap();
+        assert_eq!(ser.position(), r#"<p:a xmlns:p="urn:p">x &amp; y"#.len());
+        ser.end_elem(prefixed("p", "urn:p", "a")).unwrap();
+        let end = ser.position();
+        assert_eq!(end, output(ser).len());
+    }
+
+    #[test]
+    fn raw_markup_is_written_as_is() {
+        let mut ser = XmlSerializer::new(vec![], opts());
+        start(&mut ser, qual("a"), &[]);
+        ser.write_text("<").unwrap();
+        ser.write_raw("<b x='1'>&amp;</b>").unwrap();
+        ser.end_elem(qual("a")).unwrap();
+        assert_eq!(output(ser), "<a>&lt;<b x='1'>&amp;</b></a>");
+
+        let strict = SerializeOpts {
+            require_well_formed: true,
+            ..opts()
+        };
+        let mut ser = XmlSerializer::new(vec![], strict);
+        start(&mut ser, qual("a"), &[]);
+        assert_eq!(
+            error(ser.write_raw("<b>\0</b>").unwrap_err()),
+            SerializeError::NotXmlChar {
+                character: '\0',
+                index: 3,
+                attribute: None,
+                element: Some(qual("a")),
+            }
+        );
+    }
+
+    #[test]
+    fn xml_versions() {
+        let strict = |xml_version| SerializeOpts {
+            require_well_formed: true,
+            xml_version: xml_version,
+            ..opts()
+        };
+        let ctrl = || qual("ctrl");
+
+        let mut ser = XmlSerializer::new(vec![], strict(XmlVersion::V1_0));
+        let err = ser
+            .start_elem(qual("a"), vec![(&ctrl(), "\u{1}")].into_iter())
+            .unwrap_err();
+        assert_eq!(
+            error(err),
+            SerializeError::NotXmlChar {
+                character: '\u{1}',
+                index: 0,
+                attribute: Some(ctrl()),
+                element: Some(qual("a")),
+            }
+        );
+
+        let mut ser = XmlSerializer::new(vec![], strict(XmlVersion::V1_1));
+        start(&mut ser, qual("a"), &[(ctrl(), "\u{1}")]);
+        ser.write_text("x\u{7F}\u{85}").unwrap();
+        assert_eq!(
+            error(ser.write_comment("\u{1}").unwrap_err()),
+            SerializeError::NotXmlChar {
+                character: '\u{1}',
+                index: 0,
+                attribute: None,
+                element: Some(qual("a")),
+            }
+        );
+        ser.end_elem(qual("a")).unwrap();
+        assert_eq!(output(ser), "<a ctrl=\"&#x1;\">x&#x7F;\u{85}</a>");
     }
 }
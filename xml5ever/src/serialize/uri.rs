@@ -0,0 +1,192 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Just enough of [RFC 3986] to resolve references against `xml:base`.
+//!
+//! [RFC 3986]: https://tools.ietf.org/html/rfc3986#section-5.2
+
+struct UriRef<'a> {
+    scheme: Option<&'a str>,
+    authority: Option<&'a str>,
+    path: &'a str,
+    query: Option<&'a str>,
+    fragment: Option<&'a str>,
+}
+
+fn parse(uri: &str) -> UriRef<'_> {
+    let (uri, fragment) = match uri.find('#') {
+        Some(i) => (&uri[..i], Some(&uri[i + 1..])),
+        None => (uri, None),
+    };
+    let (uri, query) = match uri.find('?') {
+        Some(i) => (&uri[..i], Some(&uri[i + 1..])),
+        None => (uri, None),
+    };
+    let (scheme, uri) = match uri.find(|c| c == ':' || c == '/') {
+        Some(i) if i > 0 && uri.as_bytes()[i] == b':' && is_scheme(&uri[..i]) => {
+            (Some(&uri[..i]), &uri[i + 1..])
+        },
+        _ => (None, uri),
+    };
+    let (authority, path) = if uri.starts_with("//") {
+        let end = uri[2..].find('/').map_or(uri.len(), |i| i + 2);
+        (Some(&uri[2..end]), &uri[end..])
+    } else {
+        (None, uri)
+    };
+    UriRef {
+        scheme: scheme,
+        authority: authority,
+        path: path,
+        query: query,
+        fragment: fragment,
+    }
+}
+
+fn is_scheme(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic()) &&
+        s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+}
+
+fn remove_dot_segments(path: &str) -> String {
+    let mut output: Vec<&str> = vec![];
+    let mut segments = path.split('/').peekable();
+    let absolute = path.starts_with('/');
+    if absolute {
+        segments.next();
+    }
+    while let Some(segment) = segments.next() {
+        let last = segments.peek().is_none();
+        match segment {
+            "." | ".." => {
+                if segment == ".." {
+                    output.pop();
+                }
+                if last {
+                    output.push("");
+                }
+            },
+            _ => output.push(segment),
+        }
+    }
+    let joined = output.join("/");
+    if absolute {
+        format!("/{}", joined)
+    } else {
+        joined
+    }
+}
+
+fn merge(base: &UriRef, path: &str) -> String {
+    if base.authority.is_some() && base.path.is_empty() {
+        format!("/{}", path)
+    } else {
+        match base.path.rfind('/') {
+            Some(i) => format!("{}{}", &base.path[..i + 1], path),
+            None => path.to_owned(),
+        }
+    }
+}
+
+/// Resolves `reference` against `base`, as described in section 5.2.2 of
+/// RFC 3986.
+pub(crate) fn resolve(base: &str, reference: &str) -> String {
+    let base = parse(base);
+    let reference = parse(reference);
+
+    let (scheme, authority, path, query) = if reference.scheme.is_some() {
+        (
+            reference.scheme,
+            reference.authority,
+            remove_dot_segments(reference.path),
+            reference.query,
+        )
+    } else if reference.authority.is_some() {
+        (
+            base.scheme,
+            reference.authority,
+            remove_dot_segments(reference.path),
+            reference.query,
+        )
+    } else if reference.path.is_empty() {
+        (
+            base.scheme,
+            base.authority,
+            base.path.to_owned(),
+            reference.query.or(base.query),
+        )
+    } else if reference.path.starts_with('/') {
+        (
+            base.scheme,
+            base.authority,
+            remove_dot_segments(reference.path),
+            reference.query,
+        )
+    } else {
+        (
+            base.scheme,
+            base.authority,
+            remove_dot_segments(&merge(&base, reference.path)),
+            reference.query,
+        )
+    };
+
+    let mut result = String::new();
+    if let Some(scheme) = scheme {
+        result.push_str(scheme);
+        result.push(':');
+    }
+    if let Some(authority) = authority {
+        result.push_str("//");
+        result.push_str(authority);
+    }
+    result.push_str(&path);
+    if let Some(query) = query {
+        result.push('?');
+        result.push_str(query);
+    }
+    if let Some(fragment) = reference.fragment {
+        result.push('#');
+        result.push_str(fragment);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve;
+
+    #[test]
+    fn rfc3986_examples() {
+        let base = "http://a/b/c/d;p?q";
+        let cases = [
+            ("g:h", "g:h"),
+            ("g", "http://a/b/c/g"),
+            ("./g", "http://a/b/c/g"),
+            ("g/", "http://a/b/c/g/"),
+            ("/g", "http://a/g"),
+            ("//g", "http://g"),
+            ("?y", "http://a/b/c/d;p?y"),
+            ("g?y", "http://a/b/c/g?y"),
+            ("#s", "http://a/b/c/d;p?q#s"),
+            ("", "http://a/b/c/d;p?q"),
+            (".", "http://a/b/c/"),
+            ("..", "http://a/b/"),
+            ("../g", "http://a/b/g"),
+            ("../../g", "http://a/g"),
+            ("../../../g", "http://a/g"),
+            ("g/./h", "http://a/b/c/g/h"),
+            ("g/../h", "http://a/b/c/h"),
+        ];
+        for &(reference, expected) in cases.iter() {
+            assert_eq!(resolve(base, reference), expected, "{}", reference);
+        }
+    }
+}
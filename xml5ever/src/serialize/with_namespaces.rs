@@ -0,0 +1,138 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::{AttrRef, Serialize, Serializer, TraversalScope};
+use crate::{LocalName, Namespace, Prefix, QualName};
+use markup5ever::{local_name, namespace_prefix, namespace_url, ns};
+use std::io;
+
+/// Serializes a node with extra namespace declarations on its first
+/// top-level element, without touching the tree.
+///
+/// Each `(prefix, namespace)` pair is written as an `xmlns` (for `None`) or
+/// `xmlns:prefix` attribute, ahead of the element's own attributes. An
+/// element that declares the same prefix itself keeps its own declaration.
+///
+/// ```
+/// # #[macro_use] extern crate markup5ever;
+/// # extern crate xml5ever;
+/// # fn main() {}
+/// use xml5ever::serialize::{serialize, Serialize, WithNamespaces};
+/// use xml5ever::Prefix;
+///
+/// fn with_svg_prefix<T: Serialize>(node: &T) -> Vec<u8> {
+///     let decls = vec![(Some(Prefix::from("svg")), ns!(svg))];
+///     let mut out = vec![];
+///     serialize(&mut out, &WithNamespaces(node, decls), Default::default()).unwrap();
+///     out
+/// }
+/// ```
+pub struct WithNamespaces<'a, T>(pub &'a T, pub Vec<(Option<Prefix>, Namespace)>);
+
+impl<'a, T: Serialize> Serialize for WithNamespaces<'a, T> {
+    fn serialize<S>(&self, serializer: &mut S, traversal_scope: TraversalScope) -> io::Result<()>
+    where
+        S: Serializer,
+    {
+        let declarations = self
+            .1
+            .iter()
+            .map(|&(ref prefix, ref ns)| {
+                let name = match *prefix {
+                    Some(ref prefix) => QualName::new(
+                        Some(namespace_prefix!("xmlns")),
+                        ns!(xmlns),
+                        LocalName::from(&**prefix),
+                    ),
+                    None => QualName::new(None, ns!(xmlns), local_name!("xmlns")),
+                };
+                (name, ns.clone())
+            })
+            .collect();
+        let mut injector = Injector {
+            inner: serializer,
+            declarations: Some(declarations),
+        };
+        self.0.serialize(&mut injector, traversal_scope)
+    }
+}
+
+/// Forwards to another serializer, adding the declarations to the first
+/// element.
+struct Injector<'s, S> {
+    inner: &'s mut S,
+    declarations: Option<Vec<(QualName, Namespace)>>,
+}
+
+impl<'s, S: Serializer> Serializer for Injector<'s, S> {
+    fn start_elem<'a, AttrIter>(&mut self, name: QualName, attrs: AttrIter) -> io::Result<()>
+    where
+        AttrIter: Iterator<Item = AttrRef<'a>>,
+    {
+        match self.declarations.take() {
+            Some(declarations) => {
+                let attrs: Vec<AttrRef<'a>> = attrs.collect();
+                // Prefixes the element declares itself are left to it.
+                let injected = declarations
+                    .iter()
+                    .filter(|&&(ref decl, _)| {
+                        !attrs
+                            .iter()
+                            .any(|&(attr, _)| attr.expanded() == decl.expanded())
+                    })
+                    .map(|&(ref name, ref ns)| (name, &**ns));
+                // Shortens the attributes' lifetime to that of `declarations`.
+                let attrs = attrs.iter().map(|&(name, value)| (name, value));
+                self.inner.start_elem(name, injected.chain(attrs))
+            },
+            None => self.inner.start_elem(name, attrs),
+        }
+    }
+
+    fn end_elem(&mut self, name: QualName) -> io::Result<()> {
+        self.inner.end_elem(name)
+    }
+
+    fn write_text(&mut self, text: &str) -> io::Result<()> {
+        self.inner.write_text(text)
+    }
+
+    fn write_comment(&mut self, text: &str) -> io::Result<()> {
+        self.inner.write_comment(text)
+    }
+
+    fn write_doctype(&mut self, name: &str) -> io::Result<()> {
+        self.inner.write_doctype(name)
+    }
+
+    fn write_doctype_full(
+        &mut self,
+        name: &str,
+        public_id: Option<&str>,
+        system_id: Option<&str>,
+    ) -> io::Result<()> {
+        self.inner.write_doctype_full(name, public_id, system_id)
+    }
+
+    fn write_processing_instruction(&mut self, target: &str, data: &str) -> io::Result<()> {
+        self.inner.write_processing_instruction(target, data)
+    }
+
+    fn write_cdata(&mut self, text: &str) -> io::Result<()> {
+        self.inner.write_cdata(text)
+    }
+
+    fn write_raw(&mut self, raw: &str) -> io::Result<()> {
+        self.inner.write_raw(raw)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -15,7 +15,7 @@ use markup5ever::{local_name, namespace_prefix, namespace_url, ns};
 use std::borrow::Cow;
 use std::borrow::Cow::Borrowed;
 use std::collections::btree_map::Iter;
-use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 use std::fmt::{Debug, Error, Formatter};
 use std::mem;
 use std::result::Result;
@@ -58,7 +58,13 @@ impl NamespaceMapStack {
     }
 }
 
-#[doc(hidden)]
+/// One scope of namespace declarations, as made by the `xmlns` attributes of
+/// an element: a map from prefixes to the namespaces they are bound to.
+///
+/// Prefixes that a scope does not mention are resolved by outer scopes. The
+/// serializer keeps a stack of these, and `XmlSerializer::push_namespace_scope`
+/// takes the bindings for one.
+#[derive(Clone)]
 pub struct NamespaceMap {
     // Map that maps prefixes to URI.
     //
@@ -81,15 +87,14 @@ impl Debug for NamespaceMap {
 }
 
 impl NamespaceMap {
-    // Returns an empty namespace.
-    #[doc(hidden)]
+    /// Creates a scope without any bindings.
     pub fn empty() -> NamespaceMap {
         NamespaceMap {
             scope: BTreeMap::new(),
         }
     }
 
-    fn default() -> NamespaceMap {
+    pub(crate) fn default() -> NamespaceMap {
         NamespaceMap {
             scope: {
                 let mut map = BTreeMap::new();
@@ -101,17 +106,71 @@ impl NamespaceMap {
         }
     }
 
-    #[doc(hidden)]
+    /// Builds a scope with the given bindings, e.g. the declarations
+    /// inherited from the ancestors of a fragment.
+    ///
+    /// The scope is keyed by prefix, so the order of `decls` is not kept.
+    /// When a prefix is given more than once, only its last binding is
+    /// kept, as `declare` replaces bindings. A namespace given with several
+    /// prefixes keeps all of them; where the serializer has to pick one of
+    /// them, as with `minimize_attr_prefixes`, it takes the first in prefix
+    /// order, whatever the order of `decls`.
+    pub fn from_declarations<I>(decls: I) -> NamespaceMap
+    where
+        I: IntoIterator<Item = (Option<Prefix>, Namespace)>,
+    {
+        let mut map = NamespaceMap::empty();
+        for (prefix, ns) in decls {
+            map.declare(prefix, Some(ns));
+        }
+        map
+    }
+
+    /// Looks up the binding of `prefix` in this scope: `None` if the scope
+    /// does not mention it, `Some(None)` if it undeclares it.
     pub fn get(&self, prefix: &Option<Prefix>) -> Option<&Option<Namespace>> {
         self.scope.get(prefix)
     }
 
-    #[doc(hidden)]
-    pub fn get_scope_iter(&self) -> Iter<Option<Prefix>, Option<Namespace>> {
+    /// Iterates over the bindings of this scope, ordered by their prefix.
+    /// `None` as a prefix is the default namespace, and `None` as a
+    /// namespace means the prefix has been undeclared.
+    pub fn iter(&self) -> Iter<Option<Prefix>, Option<Namespace>> {
         self.scope.iter()
     }
 
-    #[doc(hidden)]
+    /// The same as `iter`.
+    pub fn get_scope_iter(&self) -> Iter<Option<Prefix>, Option<Namespace>> {
+        self.iter()
+    }
+
+    /// Iterates over the namespaces bound in this scope, each once and in
+    /// sorted order, e.g. to write a deterministic block of declarations.
+    /// Undeclared prefixes are skipped.
+    pub fn namespaces(&self) -> impl Iterator<Item = &Namespace> {
+        let namespaces: BTreeSet<&Namespace> =
+            self.scope.values().filter_map(Option::as_ref).collect();
+        namespaces.into_iter()
+    }
+
+    /// Binds `prefix` to `uri` in this scope, replacing any binding it had.
+    /// A `None` prefix is the default namespace. With a `None` uri the
+    /// prefix is undeclared, hiding the bindings of outer scopes, as with
+    /// `xmlns=""`.
+    pub fn declare(&mut self, prefix: Option<Prefix>, uri: Option<Namespace>) {
+        self.scope.insert(prefix, uri);
+    }
+
+    /// Removes the binding of `prefix` from this scope, so that an outer
+    /// scope decides what it means again. Returns whether it was bound.
+    ///
+    /// This is not the same as undeclaring it with
+    /// `declare(prefix, None)`, which hides outer bindings.
+    pub fn remove(&mut self, prefix: &Option<Prefix>) -> bool {
+        self.scope.remove(prefix).is_some()
+    }
+
+    /// Binds the prefix of `name` to its namespace in this scope.
     pub fn insert(&mut self, name: &QualName) {
         let prefix = if let Some(ref p) = name.prefix {
             Some(p.clone())
@@ -762,3 +821,82 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::NamespaceMap;
+    use crate::{Namespace, Prefix};
+
+    #[test]
+    fn namespaces_in_sorted_order() {
+        let mut map = NamespaceMap::empty();
+        map.declare(Some(Prefix::from("z")), Some(Namespace::from("urn:z")));
+        map.declare(None, Some(Namespace::from("urn:default")));
+        map.declare(Some(Prefix::from("a")), Some(Namespace::from("urn:a")));
+        map.declare(Some(Prefix::from("b")), None);
+        map.declare(Some(Prefix::from("c")), Some(Namespace::from("urn:a")));
+
+        let namespaces: Vec<_> = map.namespaces().collect();
+        assert_eq!(namespaces.len(), 3);
+        assert_eq!(
+            namespaces,
+            vec![
+                &Namespace::from("urn:a"),
+                &Namespace::from("urn:default"),
+                &Namespace::from("urn:z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn from_declarations() {
+        let map = NamespaceMap::from_declarations(vec![
+            (Some(Prefix::from("b")), Namespace::from("urn:shared")),
+            (Some(Prefix::from("a")), Namespace::from("urn:shared")),
+            (None, Namespace::from("urn:first")),
+            (None, Namespace::from("urn:default")),
+        ]);
+
+        // Ordered by prefix, not as given, and the second binding of the
+        // default namespace replaced the first.
+
+        let scope: Vec<_> = map.get_scope_iter().collect();
+        assert_eq!(
+            scope,
+            vec![
+                (&None, &Some(Namespace::from("urn:default"))),
+                (
+                    &Some(Prefix::from("a")),
+                    &Some(Namespace::from("urn:shared"))
+                ),
+                (
+                    &Some(Prefix::from("b")),
+                    &Some(Namespace::from("urn:shared"))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn remove_binding() {
+        let mut map = NamespaceMap::empty();
+        map.declare(Some(Prefix::from("a")), Some(Namespace::from("urn:a")));
+        map.declare(Some(Prefix::from("b")), None);
+        assert_eq!(
+            map.iter().collect::<Vec<_>>(),
+            vec![
+                (&Some(Prefix::from("a")), &Some(Namespace::from("urn:a"))),
+                (&Some(Prefix::from("b")), &None),
+            ]
+        );
+
+        assert!(map.remove(&Some(Prefix::from("a"))));
+        assert!(!map.remove(&Some(Prefix::from("a"))));
+        assert_eq!(map.get(&Some(Prefix::from("a"))), None);
+
+        // Removing an undeclaration is different from declaring it again.
+        assert!(map.remove(&Some(Prefix::from("b"))));
+        assert!(!map.remove(&None));
+        assert_eq!(map.iter().count(), 0);
+    }
+}
@@ -7,22 +7,12 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use mac::{_tt_as_expr_hack, matches};
+//! The character classes live in `markup5ever::xml_chars`, so that they can be
+//! shared with crates that don't depend on xml5ever. They are re-exported here
+//! under their old names.
 
-/// Is the character an ASCII alphanumeric character?
-pub fn is_ascii_alnum(c: char) -> bool {
-    matches!(c, '0'..='9' | 'a'..='z' | 'A'..='Z')
-}
-
-#[cfg(test)]
-#[allow(non_snake_case)]
-mod test {
-    use super::is_ascii_alnum;
-    use mac::test_eq;
-
-    test_eq!(is_alnum_a, is_ascii_alnum('a'), true);
-    test_eq!(is_alnum_A, is_ascii_alnum('A'), true);
-    test_eq!(is_alnum_1, is_ascii_alnum('1'), true);
-    test_eq!(is_not_alnum_symbol, is_ascii_alnum('!'), false);
-    test_eq!(is_not_alnum_nonascii, is_ascii_alnum('\u{a66e}'), false);
-}
+pub use markup5ever::xml_chars::{
+    is_ascii_alnum, is_name_char, is_name_char_11, is_name_start_char, is_name_start_char_11,
+    is_pubid_char, is_restricted_char_11, is_valid_name, is_valid_ncname, is_xml_char,
+    is_xml_char_11,
+};
@@ -0,0 +1,124 @@
+// Copyright 2014-2017 The html5ever Project Developers. See the
+// COPYRIGHT file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![cfg(feature = "tokio")]
+
+#[macro_use]
+extern crate markup5ever;
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::ptr;
+use std::rc::Rc;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+use tokio::io::AsyncWrite;
+use xml5ever::serialize::{AsyncXmlSerializer, Serialize, Serializer, TraversalScope};
+use xml5ever::QualName;
+
+struct Doc;
+
+impl Serialize for Doc {
+    fn serialize<S: Serializer>(&self, ser: &mut S, _: TraversalScope) -> io::Result<()> {
+        let name = QualName::new(None, ns!(), local_name!("p"));
+        ser.start_elem(name.clone(), None.into_iter())?;
+        ser.write_text("a < b")?;
+        ser.end_elem(name)
+    }
+}
+
+/// A writer that keeps every write it gets, to check how output is split.
+#[derive(Clone, Default)]
+struct Recorder {
+    writes: Rc<RefCell<Vec<Vec<u8>>>>,
+}
+
+impl Recorder {
+    fn output(&self) -> String {
+        String::from_utf8(self.writes.borrow().concat()).unwrap()
+    }
+}
+
+impl AsyncWrite for Recorder {
+    fn poll_write(self: Pin<&mut Self>, _: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.writes.borrow_mut().push(buf.to_vec());
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Runs a future that never has to wait, as writing to memory doesn't.
+fn block_on<F: Future>(future: F) -> F::Output {
+    fn raw_waker() -> RawWaker {
+        fn clone(_: *const ()) -> RawWaker {
+            raw_waker()
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        RawWaker::new(ptr::null(), &VTABLE)
+    }
+
+    let waker = unsafe { Waker::from_raw(raw_waker()) };
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    match Pin::as_mut(&mut future).poll(&mut cx) {
+        Poll::Ready(output) => output,
+        Poll::Pending => panic!("writing to memory should not have to wait"),
+    }
+}
+
+#[test]
+fn write_node_to_async_writer() {
+    let mut ser = AsyncXmlSerializer::new(Vec::new(), Default::default());
+    block_on(ser.write_node(&Doc, TraversalScope::IncludeNode)).unwrap();
+    let out = block_on(ser.finish()).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "<p>a &lt; b</p>");
+}
+
+#[test]
+fn output_is_written_in_bounded_chunks() {
+    let recorder = Recorder::default();
+    let mut ser = AsyncXmlSerializer::with_chunk_size(recorder.clone(), Default::default(), 8);
+    let list = QualName::new(None, ns!(), local_name!("ul"));
+    let item = QualName::new(None, ns!(), local_name!("li"));
+
+    block_on(ser.start_elem(list.clone(), None.into_iter())).unwrap();
+    assert!(recorder.writes.borrow().is_empty());
+    for _ in 0..3 {
+        block_on(ser.start_elem(item.clone(), None.into_iter())).unwrap();
+        block_on(ser.write_text("item")).unwrap();
+        block_on(ser.end_elem(item.clone())).unwrap();
+    }
+    // Whole chunks go out as they fill up, before the document is done.
+    assert_eq!(
+        recorder.output(),
+        "<ul><li>item</li><li>item</li><li>item</"
+    );
+    assert!(recorder.writes.borrow().iter().all(|w| w.len() == 8));
+
+    block_on(ser.end_elem(list)).unwrap();
+    block_on(ser.finish()).unwrap();
+    assert_eq!(
+        recorder.output(),
+        "<ul><li>item</li><li>item</li><li>item</li></ul>"
+    );
+    let writes = recorder.writes.borrow();
+    let (last, rest) = writes.split_last().unwrap();
+    assert!(rest.iter().all(|w| w.len() == 8));
+    assert!(last.len() <= 8);
+}